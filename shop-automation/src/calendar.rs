@@ -0,0 +1,284 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::fs;
+use std::path::Path;
+use crate::menu::Menu;
+
+// iCalendar export of a `.menu`: an event per meal, a reminder for notes like
+// "-- do prep for burrito" and the delivery slot from "-- delivery around 4pm".
+// Times are floating (no time zone) so they show as written wherever the
+// calendar is opened.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// iCalendar, for importing into a shared calendar
+    Ics,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Ics => "ics",
+        }
+    }
+}
+
+/// When meals go in the calendar, and for how many minutes
+const MEAL_TIMES: [(&str, u32, u32); 3] = [("breakfast", 8, 30), ("lunch", 13, 45), ("dinner", 19, 60)];
+
+/// Prep notes without a time are evening jobs
+const PREP_HOUR: u32 = 20;
+
+struct Event {
+    uid: String,
+    date: NaiveDate,
+    /// `None` for an all-day event
+    start: Option<(NaiveTime, u32)>,
+    summary: String,
+    description: String,
+    /// Minutes before the start to remind
+    alarm: Option<u32>,
+}
+
+/// "around 4pm", "at 4:30 pm", "by 16:00" -> the time
+pub fn parse_time(note: &str) -> Option<NaiveTime> {
+    let words: Vec<String> = note
+        .to_lowercase()
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != ':').to_string())
+        .collect();
+
+    for (i, word) in words.iter().enumerate() {
+        let (clock, suffix) = match word.find(|c: char| c.is_alphabetic()) {
+            Some(split) => (&word[..split], &word[split..]),
+            None => (word.as_str(), words.get(i + 1).map(String::as_str).unwrap_or_default()),
+        };
+        if clock.is_empty() || !clock.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        let (hour, minute) = clock.split_once(':').unwrap_or((clock, "0"));
+        let (Ok(mut hour), Ok(minute)) = (hour.parse::<u32>(), minute.parse::<u32>()) else {
+            continue;
+        };
+        match suffix {
+            "pm" if hour < 12 => hour += 12,
+            "am" if hour == 12 => hour = 0,
+            "am" | "pm" => {}
+            // A bare number is only a time with minutes: "16:00"
+            _ if !clock.contains(':') => continue,
+            _ => {}
+        }
+        if let Some(time) = NaiveTime::from_hms_opt(hour, minute, 0) {
+            return Some(time);
+        }
+    }
+    None
+}
+
+fn capitalized(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn menu_events(menu: &Menu, plan: &str, start: NaiveDate) -> Result<Vec<Event>> {
+    let Some(first_day) = menu.days().filter_map(|section| section.day).min() else {
+        bail!("the menu has no ==Day N== sections");
+    };
+    let plan_slug = slug(plan);
+
+    let mut events = Vec::new();
+    for section in menu.days() {
+        let date = start + Duration::days((section.day.unwrap_or(first_day) - first_day) as i64);
+        let uid = |what: &str| format!("{}-{}-{}@shop-automation", plan_slug, date.format("%Y%m%d"), slug(what));
+
+        // Notes that aren't a delivery or prep go with the day's meals
+        let mut other_notes = Vec::new();
+        for note in &section.notes {
+            let lower = note.to_lowercase();
+            if lower.contains("deliver") {
+                events.push(Event {
+                    uid: uid("delivery"),
+                    date,
+                    start: parse_time(note).map(|time| (time, 60)),
+                    summary: "🛒 Grocery delivery".to_string(),
+                    description: capitalized(note),
+                    alarm: Some(30),
+                });
+            } else if lower.contains("prep") {
+                let time = parse_time(note).unwrap_or_else(|| NaiveTime::from_hms_opt(PREP_HOUR, 0, 0).expect("valid hour"));
+                events.push(Event {
+                    uid: uid(note),
+                    date,
+                    start: Some((time, 30)),
+                    summary: format!("🔪 {}", capitalized(note)),
+                    description: format!("{}, {}", plan, section.title),
+                    alarm: Some(15),
+                });
+            } else if lower != "nothing" {
+                other_notes.push(capitalized(note));
+            }
+        }
+
+        for meal in &section.meals {
+            if meal.dishes.is_empty() || meal.name.is_empty() {
+                continue;
+            }
+            let (hour, minutes) = MEAL_TIMES
+                .iter()
+                .find(|(name, _, _)| meal.name.eq_ignore_ascii_case(name))
+                .map_or((12, 30), |&(_, hour, minutes)| (hour, minutes));
+            let time = NaiveTime::from_hms_opt(hour, 0, 0).expect("valid hour");
+
+            let titles: Vec<String> = meal.dishes.iter().map(|dish| dish.title()).collect();
+            let mut description: Vec<String> = titles.iter().map(|title| format!("- {}", title)).collect();
+            description.extend(other_notes.iter().cloned());
+            description.push(format!("{}, {}", plan, section.title));
+
+            events.push(Event {
+                uid: uid(&meal.name),
+                date,
+                start: Some((time, minutes)),
+                summary: format!("{}: {}", meal.name, titles.join(", ")),
+                description: description.join("\n"),
+                alarm: None,
+            });
+        }
+    }
+
+    events.sort_by_key(|event| (event.date, event.start.map(|(time, _)| time)));
+    Ok(events)
+}
+
+/// Text values escape `\`, `;`, `,` and newlines
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Content lines are folded at 75 bytes, continuing with a space
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn render(events: &[Event], name: &str) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let format = |at: NaiveDateTime| at.format("%Y%m%dT%H%M%S").to_string();
+
+    let mut ics = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//shop-automation//menu plans//EN", "CALSCALE:GREGORIAN"] {
+        push_line(&mut ics, line);
+    }
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape(name)));
+
+    for event in events {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}", event.uid));
+        push_line(&mut ics, &format!("DTSTAMP:{}", stamp));
+        match event.start {
+            Some((time, minutes)) => {
+                let start = event.date.and_time(time);
+                push_line(&mut ics, &format!("DTSTART:{}", format(start)));
+                push_line(&mut ics, &format!("DTEND:{}", format(start + Duration::minutes(minutes as i64))));
+            }
+            None => {
+                push_line(&mut ics, &format!("DTSTART;VALUE=DATE:{}", event.date.format("%Y%m%d")));
+                push_line(&mut ics, &format!("DTEND;VALUE=DATE:{}", (event.date + Duration::days(1)).format("%Y%m%d")));
+            }
+        }
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&event.summary)));
+        push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&event.description)));
+        if let Some(minutes) = event.alarm {
+            push_line(&mut ics, "BEGIN:VALARM");
+            push_line(&mut ics, "ACTION:DISPLAY");
+            push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&event.summary)));
+            push_line(&mut ics, &format!("TRIGGER:-PT{}M", minutes));
+            push_line(&mut ics, "END:VALARM");
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// Write `menu_path` as an `.ics` calendar, its first day on `start`
+pub fn export_ics(menu_path: &Path, start: NaiveDate, output: &Path) -> Result<()> {
+    let menu = Menu::load(menu_path)?;
+    let plan = menu_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let events = menu_events(&menu, &plan, start).context(format!("Can't export {}", menu_path.display()))?;
+
+    fs::write(output, render(&events, &plan)).context(format!("Failed to write {:?}", output))?;
+
+    for event in &events {
+        let when = match event.start {
+            Some((time, _)) => format!("{} {}", event.date, time.format("%H:%M")),
+            None => format!("{} all day", event.date),
+        };
+        println!("   {}  {}", when, event.summary);
+    }
+    println!("\n📅 Wrote {} events to {}", events.len(), output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    #[test]
+    fn finds_times_in_notes() {
+        assert_eq!(parse_time("delivery around 4pm"), time(16, 0));
+        assert_eq!(parse_time("delivery at 4:30 pm"), time(16, 30));
+        assert_eq!(parse_time("by 16:00"), time(16, 0));
+        assert_eq!(parse_time("prep at 12am"), time(0, 0));
+        assert_eq!(parse_time("marinate for 45 minutes"), None);
+        assert_eq!(parse_time("do prep for burrito"), None);
+        assert_eq!(parse_time("at 25:00"), None);
+    }
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(escape("Pasta, salad; dessert"), "Pasta\\, salad\\; dessert");
+        assert_eq!(escape("a\\b\nc"), "a\\\\b\\nc");
+    }
+
+    #[test]
+    fn folds_lines_at_75_bytes() {
+        let mut ics = String::new();
+        push_line(&mut ics, &"x".repeat(160));
+        let lines: Vec<&str> = ics.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.iter().map(|line| line.len()).collect::<Vec<_>>(), [75, 75, 12]);
+        assert!(lines[1].starts_with(' ') && lines[2].starts_with(' '));
+
+        // Multi-byte characters aren't split
+        let mut ics = String::new();
+        push_line(&mut ics, &"é".repeat(40));
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(ics.replace("\r\n ", "").trim_end(), "é".repeat(40));
+    }
+}
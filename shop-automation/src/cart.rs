@@ -0,0 +1,644 @@
+use anyhow::{Context, Result, bail};
+use headless_chrome::Tab;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::price;
+use crate::profile::Profile;
+use crate::shopper;
+
+// The SuperValu basket read back from the cart page, so what was actually
+// bought can be printed, exported and compared with the shopping list.
+
+const SUPERVALU_BASE_URL: &str = "https://shop.supervalu.ie";
+
+/// Times `cart clear` goes over the cart before giving up
+const CLEAR_PASSES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cart {
+    pub items: Vec<CartItem>,
+    /// Sum of the lines as the store shows it
+    pub subtotal: Option<f64>,
+    /// Delivery, service and bag charges
+    pub fees: Vec<Fee>,
+    /// Subtotal plus fees, as the store shows it
+    pub total: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartItem {
+    /// The digits after `-id-` in the product URL
+    pub product_id: Option<String>,
+    pub name: String,
+    pub url: Option<String>,
+    /// Packs on the line; `None` when the page didn't show a number
+    pub quantity: Option<u32>,
+    /// Price of one pack
+    pub unit_price: Option<f64>,
+    pub line_total: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fee {
+    pub name: String,
+    pub amount: f64,
+}
+
+impl Cart {
+    /// Packs in the cart, leaving out lines with an unknown quantity
+    pub fn item_count(&self) -> u32 {
+        self.items.iter().filter_map(|item| item.quantity).sum()
+    }
+}
+
+impl CartItem {
+    /// The quantity for printing, "?" when unknown
+    fn shown_quantity(&self) -> String {
+        self.quantity.map_or("?".to_string(), |quantity| quantity.to_string())
+    }
+}
+
+/// `https://.../product/brennans-bread-id-1234567` -> "1234567"
+pub fn product_id(url: &str) -> Option<&str> {
+    let slug = url.split(['?', '#']).next()?.rsplit('/').next()?;
+    let (_, id) = slug.rsplit_once("-id-")?;
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then_some(id)
+}
+
+/// A product a run meant to have in the cart
+#[derive(Debug, Clone)]
+pub struct Expected {
+    /// Shopping list item it was added for
+    pub item: String,
+    pub url: String,
+    pub packs: u32,
+    /// Product ids of the item's other options; one of those in the cart
+    /// instead counts as a substitute rather than an extra
+    pub alternatives: Vec<String>,
+    /// Still wanted, but the run couldn't add it (out of stock, say), so
+    /// there's no point in adding it again
+    pub unavailable: bool,
+}
+
+impl Expected {
+    fn matches(&self, line: &CartItem) -> bool {
+        line.url.as_deref().is_some_and(|url| same_product(url, &self.url))
+    }
+}
+
+/// Same product id, or the same URL for links without one
+pub fn same_product(a: &str, b: &str) -> bool {
+    match (product_id(a), product_id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Where the cart and the shopping list disagree
+#[derive(Debug, Default)]
+pub struct CartDiff {
+    /// Added by the run but not in the cart
+    pub missing: Vec<Expected>,
+    /// Wanted but not in the cart because the run couldn't add them
+    pub unavailable: Vec<Expected>,
+    /// In the cart but not asked for, e.g. left over from last week
+    pub extras: Vec<CartItem>,
+    /// In the cart with a different number of packs than asked for
+    pub wrong_quantity: Vec<(Expected, CartItem)>,
+    /// Another option of the item is in the cart instead of the one added
+    pub substituted: Vec<(Expected, CartItem)>,
+}
+
+impl CartDiff {
+    pub fn is_empty(&self) -> bool {
+        self.unavailable.is_empty() && !self.is_fixable()
+    }
+
+    /// Anything `--fix-cart` can do something about
+    pub fn is_fixable(&self) -> bool {
+        !(self.missing.is_empty() && self.extras.is_empty() && self.wrong_quantity.is_empty() && self.substituted.is_empty())
+    }
+}
+
+/// Compare the cart with what was meant to be in it. The same product asked
+/// for by two list items counts once, with their packs added up.
+pub fn diff(cart: &Cart, expected: &[Expected]) -> CartDiff {
+    let mut merged: Vec<Expected> = Vec::new();
+    for wanted in expected {
+        match merged.iter_mut().find(|m| same_product(&m.url, &wanted.url)) {
+            Some(existing) => {
+                existing.item = format!("{}, {}", existing.item, wanted.item);
+                existing.packs += wanted.packs;
+                existing.alternatives.extend(wanted.alternatives.iter().cloned());
+                existing.unavailable &= wanted.unavailable;
+            }
+            None => merged.push(wanted.clone()),
+        }
+    }
+
+    let mut claimed = vec![false; cart.items.len()];
+    let mut result = CartDiff::default();
+    let mut unmatched = Vec::new();
+    for wanted in merged {
+        match cart.items.iter().position(|line| wanted.matches(line)) {
+            Some(index) => {
+                claimed[index] = true;
+                // A line whose quantity couldn't be read isn't reported as wrong
+                if cart.items[index].quantity.is_some_and(|quantity| quantity != wanted.packs) {
+                    result.wrong_quantity.push((wanted, cart.items[index].clone()));
+                }
+            }
+            None => unmatched.push(wanted),
+        }
+    }
+
+    // Only lines nobody asked for can stand in for a missing product
+    for wanted in unmatched {
+        let substitute = cart.items.iter().enumerate().position(|(index, line)| {
+            !claimed[index] && line.product_id.as_ref().is_some_and(|id| wanted.alternatives.contains(id))
+        });
+        match substitute {
+            Some(index) => {
+                claimed[index] = true;
+                result.substituted.push((wanted, cart.items[index].clone()));
+            }
+            None if wanted.unavailable => result.unavailable.push(wanted),
+            None => result.missing.push(wanted),
+        }
+    }
+
+    result.extras = cart.items
+        .iter()
+        .zip(&claimed)
+        .filter(|(_, claimed)| !**claimed)
+        .map(|(line, _)| line.clone())
+        .collect();
+    result
+}
+
+pub fn print_diff(diff: &CartDiff) {
+    if diff.is_empty() {
+        println!("✅ The cart matches the shopping list");
+        return;
+    }
+
+    println!("\x1b[33m⚖️  The cart doesn't match the shopping list:\x1b[0m");
+    for wanted in &diff.missing {
+        println!("   ❌ Missing: {} × {} ({})", wanted.packs, wanted.item, wanted.url);
+    }
+    for wanted in &diff.unavailable {
+        println!("   🚫 Missing, couldn't be added: {} × {}", wanted.packs, wanted.item);
+    }
+    for (wanted, line) in &diff.wrong_quantity {
+        println!("   🔢 {}: {} in the cart, {} wanted", wanted.item, line.shown_quantity(), wanted.packs);
+    }
+    for (wanted, line) in &diff.substituted {
+        println!("   🔁 {}: {} in the cart instead of the product added", wanted.item, line.name);
+    }
+    for line in &diff.extras {
+        println!("   ➕ Not on the list: {} × {}", line.shown_quantity(), line.name);
+    }
+}
+
+/// What the page script returns, before the prices are parsed
+#[derive(Deserialize)]
+struct RawCart {
+    items: Vec<RawItem>,
+    summary: Vec<RawSummaryLine>,
+}
+
+#[derive(Deserialize)]
+struct RawItem {
+    name: String,
+    url: Option<String>,
+    quantity: String,
+    /// Euro amounts on the line, without per-kg prices or "was" prices
+    prices: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSummaryLine {
+    label: String,
+    amount: String,
+}
+
+/// Open the cart page and read it
+pub fn read(tab: &Tab) -> Result<Cart> {
+    tab.navigate_to(&format!("{}/cart", SUPERVALU_BASE_URL))?;
+    std::thread::sleep(Duration::from_secs(3));
+
+    // A line is the nearest ancestor of a product link that also holds its
+    // quantity control, so prices and names can't leak between lines
+    let cart_js = r#"
+        (() => {
+            const text = el => (el?.textContent || '').trim().replace(/\s+/g, ' ');
+            const control = 'input[type="number"], select, [aria-label*="quantity" i], button[aria-label*="increase" i], button[aria-label*="decrease" i], button[aria-label*="remove" i]';
+
+            const rows = new Map();
+            for (const link of document.querySelectorAll('a[href*="/product/"]')) {
+                let row = link.parentElement;
+                while (row && row !== document.body && !row.querySelector(control)) {
+                    row = row.parentElement;
+                }
+                if (row && row !== document.body && !rows.has(row)) {
+                    rows.set(row, link);
+                }
+            }
+
+            const items = [...rows].map(([row, link]) => {
+                const quantityEl = row.querySelector('input[type="number"], input[aria-label*="quantity" i], select, [data-testid*="quantity" i]');
+                const quantity = quantityEl ? (quantityEl.value || text(quantityEl)) : '';
+                const prices = [...row.querySelectorAll('*')]
+                    .filter(el => el.children.length === 0 && text(el).includes('€'))
+                    .map(text)
+                    .filter(t => !/\/\s*(kg|l|ml|g|100g|100ml)\b|was|save|deposit/i.test(t));
+                return {
+                    name: text(row.querySelector('[data-testid*="name" i]')) || text(link) || link.getAttribute('aria-label') || '',
+                    url: link.href,
+                    quantity: String(quantity),
+                    prices,
+                };
+            });
+
+            // Summary lines are a short label next to an amount, outside the lines
+            const lines = [...rows.keys()];
+            const summary = [];
+            for (const el of document.querySelectorAll('div, li, tr, dl, p')) {
+                const t = text(el);
+                if (t.length > 60 || lines.some(row => row.contains(el) || el.contains(row))) continue;
+                const match = t.match(/^([A-Za-z][A-Za-z &'()-]{1,40}?)\s*:?\s*(-?€\s?\d[\d,]*\.\d{2}|free)$/i);
+                if (match) summary.push({ label: match[1].trim(), amount: match[2] });
+            }
+
+            return JSON.stringify({ items, summary });
+        })()
+    "#;
+
+    let result = tab.evaluate(cart_js, false)?;
+    let json = result.value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .context("The cart page didn't return anything")?;
+    let raw: RawCart = serde_json::from_str(json).context("Failed to read the cart page")?;
+    Ok(parse(raw))
+}
+
+fn parse(raw: RawCart) -> Cart {
+    let items = raw.items
+        .into_iter()
+        .map(|item| {
+            let quantity = item.quantity
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|part| !part.is_empty())
+                .and_then(|digits| digits.parse::<u32>().ok());
+            let amounts: Vec<f64> = item.prices.iter().filter_map(|p| price::parse_euros(p)).collect();
+            let smallest = amounts.iter().copied().reduce(f64::min);
+            let largest = amounts.iter().copied().reduce(f64::max);
+            // One amount on a line of several packs is the line total
+            let several = quantity.filter(|&quantity| quantity > 1);
+            let (unit_price, line_total) = match (smallest, largest, several) {
+                (Some(unit), Some(total), _) if amounts.len() > 1 => (Some(unit), Some(total)),
+                (Some(total), _, Some(packs)) => (Some(total / packs as f64), Some(total)),
+                (Some(price), _, _) => (Some(price), Some(price)),
+                _ => (None, None),
+            };
+            CartItem {
+                product_id: item.url.as_deref().and_then(product_id).map(String::from),
+                name: item.name,
+                url: item.url,
+                quantity,
+                unit_price,
+                line_total,
+            }
+        })
+        .collect();
+
+    let mut cart = Cart { items, ..Default::default() };
+    for line in raw.summary {
+        let label = line.label.to_lowercase();
+        let amount = if line.amount.eq_ignore_ascii_case("free") {
+            Some(0.0)
+        } else {
+            price::parse_euros(&line.amount)
+        };
+        let Some(amount) = amount else {
+            continue;
+        };
+
+        // Nested elements repeat the same line; the first one wins
+        if label.contains("subtotal") {
+            cart.subtotal.get_or_insert(amount);
+        } else if label.contains("total") {
+            cart.total.get_or_insert(amount);
+        } else if ["fee", "charge", "delivery", "service", "bag", "levy"].iter().any(|word| label.contains(word))
+            && !cart.fees.iter().any(|fee| fee.name.eq_ignore_ascii_case(&line.label))
+        {
+            cart.fees.push(Fee { name: line.label, amount });
+        }
+    }
+    cart
+}
+
+pub fn print(cart: &Cart) {
+    if cart.items.is_empty() {
+        println!("🛒 The cart is empty");
+    } else {
+        println!("🛒 Cart: {} items", cart.item_count());
+        for item in &cart.items {
+            let unit_price = item.unit_price.map(|p| format!("€{:.2}", p)).unwrap_or_else(|| "?".to_string());
+            let line_total = item.line_total.map(|p| format!("€{:.2}", p)).unwrap_or_else(|| "?".to_string());
+            let id = item.product_id.as_deref().map(|id| format!(" (id {})", id)).unwrap_or_default();
+            println!("   {:>2} × {}{}  {} each  {}", item.shown_quantity(), item.name, id, unit_price, line_total);
+        }
+    }
+
+    if let Some(subtotal) = cart.subtotal {
+        println!("💰 Subtotal: €{:.2}", subtotal);
+    }
+    for fee in &cart.fees {
+        println!("   {}: €{:.2}", fee.name, fee.amount);
+    }
+    if let Some(total) = cart.total {
+        println!("💶 Total: €{:.2}", total);
+    }
+}
+
+/// Take a line out of the cart through its product page's stepper
+pub fn remove_line(tab: &Tab, line: &CartItem) -> Result<bool> {
+    match &line.url {
+        Some(url) => shopper::change_cart_quantity(tab, url, 0),
+        None => Ok(false),
+    }
+}
+
+/// Lines picked by their exact product id or URL, else by name: the whole
+/// name if a line has it, otherwise whole words of it ("milk" picks
+/// "Milk 2L" but not "Buttermilk"). Case doesn't matter for names.
+fn matching<'a>(cart: &'a Cart, query: &str) -> Vec<&'a CartItem> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let by_product: Vec<&CartItem> = cart.items
+        .iter()
+        .filter(|line| {
+            line.product_id.as_deref() == Some(query)
+                || line.url.as_deref().is_some_and(|url| same_product(url, query))
+        })
+        .collect();
+    if !by_product.is_empty() {
+        return by_product;
+    }
+
+    let whole_name: Vec<&CartItem> = cart.items.iter().filter(|line| line.name.trim().eq_ignore_ascii_case(query)).collect();
+    if !whole_name.is_empty() {
+        return whole_name;
+    }
+    cart.items.iter().filter(|line| contains_words(&line.name, query)).collect()
+}
+
+/// `words` appears in `name` starting and ending on word boundaries
+fn contains_words(name: &str, words: &str) -> bool {
+    let name = name.to_lowercase();
+    let words = words.to_lowercase();
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    name.match_indices(&words).any(|(start, found)| {
+        !is_word(name[..start].chars().next_back()) && !is_word(name[start + found.len()..].chars().next())
+    })
+}
+
+/// `cart remove`: take the lines matching each query out of the cart. A
+/// query matching several lines only removes them with `all`.
+pub fn remove(profile: &Profile, visible: bool, queries: &[String], all: bool) -> Result<()> {
+    if queries.iter().any(|query| query.trim().is_empty()) {
+        bail!("Give a product id, product URL or name to remove");
+    }
+    let (_browser, tab) = shopper::open_session(profile, visible, false)?;
+    let cart = read(&tab)?;
+
+    let mut picked: Vec<&CartItem> = Vec::new();
+    for query in queries {
+        let lines = matching(&cart, query);
+        if lines.is_empty() {
+            println!("\x1b[33m⚠️  Nothing in the cart matches '{}'\x1b[0m", query);
+        }
+        if lines.len() > 1 && !all {
+            println!("\x1b[33m⚠️  '{}' matches {} lines, left in the cart:\x1b[0m", query, lines.len());
+            for line in &lines {
+                let id = line.product_id.as_deref().map(|id| format!(" (id {})", id)).unwrap_or_default();
+                println!("   - {} × {}{}", line.shown_quantity(), line.name, id);
+            }
+            println!("   Remove one by its product id, or all of them with --all");
+            continue;
+        }
+        for line in lines {
+            if !picked.iter().any(|p| std::ptr::eq(*p, line)) {
+                picked.push(line);
+            }
+        }
+    }
+    if picked.is_empty() {
+        bail!("Nothing to remove");
+    }
+
+    for line in picked {
+        if remove_line(&tab, line)? {
+            println!("🗑️  Removed {} × {}", line.shown_quantity(), line.name);
+        } else {
+            println!("\x1b[33m⚠️  Couldn't remove {}, do it in the cart\x1b[0m", line.name);
+        }
+    }
+
+    println!();
+    print(&read(&tab)?);
+    Ok(())
+}
+
+/// `cart clear`: empty the cart so a run starts from nothing
+pub fn clear(profile: &Profile, visible: bool) -> Result<()> {
+    let (_browser, tab) = shopper::open_session(profile, visible, false)?;
+    let mut cart = read(&tab)?;
+    if cart.items.is_empty() {
+        println!("🛒 The cart is already empty");
+        return Ok(());
+    }
+
+    println!("🗑️  Removing {} lines...", cart.items.len());
+    // A second pass catches lines the page only showed after others went
+    for _ in 0..CLEAR_PASSES {
+        for line in &cart.items {
+            if remove_line(&tab, line)? {
+                println!("   Removed {} × {}", line.shown_quantity(), line.name);
+            }
+        }
+        cart = read(&tab)?;
+        if cart.items.is_empty() {
+            println!("✅ The cart is empty");
+            return Ok(());
+        }
+    }
+
+    println!("\x1b[33m⚠️  {} lines are still in the cart, remove them by hand:\x1b[0m", cart.items.len());
+    for line in &cart.items {
+        println!("   - {} × {}", line.shown_quantity(), line.name);
+    }
+    Ok(())
+}
+
+/// `cart show`: read the cart of the logged-in session and print or export it.
+/// JSON and YAML go to `output`, as login and progress lines go to stdout.
+pub fn show(profile: &Profile, visible: bool, format: Format, output: Option<&Path>) -> Result<()> {
+    let (_browser, tab) = shopper::open_session(profile, visible, false)?;
+    let cart = read(&tab)?;
+
+    let exported = match format {
+        Format::Table => None,
+        Format::Json => Some(serde_json::to_string_pretty(&cart)?),
+        Format::Yaml => Some(serde_yaml::to_string(&cart)?),
+    };
+    // clap requires --output for json and yaml
+    let (Some(exported), Some(path)) = (exported, output) else {
+        println!();
+        print(&cart);
+        return Ok(());
+    };
+    fs::write(path, exported).context(format!("Failed to write {:?}", path))?;
+    println!("\n💾 Wrote {} cart lines to {}", cart.items.len(), path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(name: &str, url: &str, quantity: u32) -> CartItem {
+        CartItem {
+            product_id: product_id(url).map(String::from),
+            name: name.to_string(),
+            url: Some(url.to_string()),
+            quantity: Some(quantity),
+            unit_price: None,
+            line_total: None,
+        }
+    }
+
+    fn wanted(item: &str, url: &str, packs: u32, alternatives: &[&str]) -> Expected {
+        Expected {
+            item: item.to_string(),
+            url: url.to_string(),
+            packs,
+            alternatives: alternatives.iter().map(|id| id.to_string()).collect(),
+            unavailable: false,
+        }
+    }
+
+    const MILK: &str = "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/milk-2l-id-1001";
+    const MILK_LOW_FAT: &str = "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/low-fat-milk-2l-id-1002";
+    const BREAD: &str = "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/brennans-bread-id-2001";
+
+    #[test]
+    fn reads_product_ids_from_urls() {
+        assert_eq!(product_id(MILK), Some("1001"));
+        assert_eq!(product_id(&format!("{}?q=milk", BREAD)), Some("2001"));
+        assert_eq!(product_id("https://shop.supervalu.ie/cart"), None);
+    }
+
+    #[test]
+    fn merges_the_same_product_from_two_items() {
+        let cart = Cart { items: vec![line("Milk 2L", MILK, 3)], ..Default::default() };
+        let expected = [wanted("milk", MILK, 1, &[]), wanted("porridge milk", &format!("{}?ref=list", MILK), 2, &[])];
+
+        assert!(diff(&cart, &expected).is_empty());
+
+        let cart = Cart { items: vec![line("Milk 2L", MILK, 1)], ..Default::default() };
+        let result = diff(&cart, &expected);
+        assert_eq!(result.wrong_quantity.len(), 1);
+        assert_eq!(result.wrong_quantity[0].0.packs, 3);
+        assert_eq!(result.wrong_quantity[0].0.item, "milk, porridge milk");
+    }
+
+    #[test]
+    fn leaves_unread_quantities_alone() {
+        let mut milk = line("Milk 2L", MILK, 1);
+        milk.quantity = None;
+        let cart = Cart { items: vec![milk], ..Default::default() };
+
+        assert!(diff(&cart, &[wanted("milk", MILK, 2, &[])]).is_empty());
+        assert_eq!(cart.item_count(), 0);
+    }
+
+    #[test]
+    fn leaves_unread_quantities_out_of_the_count() {
+        let mut milk = line("Milk 2L", MILK, 1);
+        milk.quantity = None;
+        let cart = Cart { items: vec![milk, line("Brennans Bread", BREAD, 2)], ..Default::default() };
+
+        assert_eq!(cart.item_count(), 2);
+        assert_eq!(cart.items[0].shown_quantity(), "?");
+    }
+
+    #[test]
+    fn matches_ids_and_urls_exactly_and_names_by_word() {
+        let cart = Cart {
+            items: vec![
+                line("Milk 2L", MILK, 1),
+                line("Milk Chocolate Digestives", "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/digestives-id-3001", 1),
+                line("Buttermilk", "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/buttermilk-id-3002", 1),
+            ],
+            ..Default::default()
+        };
+        let names = |query: &str| matching(&cart, query).iter().map(|line| line.name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names("1001"), ["Milk 2L"]);
+        assert!(names("100").is_empty());
+        assert_eq!(names(MILK), ["Milk 2L"]);
+        assert_eq!(names("milk 2l"), ["Milk 2L"]);
+        assert_eq!(names("milk"), ["Milk 2L", "Milk Chocolate Digestives"]);
+        assert_eq!(names("chocolate"), ["Milk Chocolate Digestives"]);
+        assert!(names("choc").is_empty());
+        assert!(names(" ").is_empty());
+    }
+
+    #[test]
+    fn counts_another_option_as_a_substitute() {
+        let cart = Cart { items: vec![line("Low Fat Milk 2L", MILK_LOW_FAT, 1)], ..Default::default() };
+        let result = diff(&cart, &[wanted("milk", MILK, 1, &["1002"])]);
+
+        assert_eq!(result.substituted.len(), 1);
+        assert_eq!(result.substituted[0].1.name, "Low Fat Milk 2L");
+        assert!(result.missing.is_empty());
+        assert!(result.extras.is_empty());
+    }
+
+    #[test]
+    fn keeps_items_that_could_not_be_added_apart() {
+        let cart = Cart::default();
+        let result = diff(&cart, &[Expected { unavailable: true, ..wanted("milk", MILK, 1, &[]) }]);
+
+        assert!(result.missing.is_empty());
+        assert_eq!(result.unavailable.len(), 1);
+        assert!(!result.is_empty());
+        assert!(!result.is_fixable());
+    }
+
+    #[test]
+    fn reports_missing_and_extra_lines() {
+        let cart = Cart { items: vec![line("Brennans Bread", BREAD, 1)], ..Default::default() };
+        let result = diff(&cart, &[wanted("milk", MILK, 1, &[])]);
+
+        assert_eq!(result.missing.len(), 1);
+        assert_eq!(result.missing[0].item, "milk");
+        assert_eq!(result.extras.len(), 1);
+        assert_eq!(result.extras[0].name, "Brennans Bread");
+    }
+}
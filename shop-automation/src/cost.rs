@@ -0,0 +1,259 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::models::{ProductOption, ShoppingData};
+use crate::names::{self, NameResolver};
+use crate::pantry;
+use crate::price::{self, PriceUnit};
+use crate::recipes;
+use crate::settings::OptionPolicy;
+
+// Estimated cost of a recipe or menu from the db prices: what the ingredients
+// used are worth, and what the packs we'd have to buy for them cost.
+
+/// Everything a recipe or menu needs of one product
+#[derive(Default)]
+struct Need {
+    name: String,
+    /// Measured amounts, summed per unit
+    amounts: Vec<(f64, PriceUnit)>,
+    /// Uses like `@salt` or `{2%cloves}` that can't be measured
+    unmeasured: bool,
+}
+
+impl Need {
+    fn add(&mut self, amount: f64, unit: PriceUnit) {
+        match self.amounts.iter_mut().find(|(_, u)| *u == unit) {
+            Some((total, _)) => *total += amount,
+            None => self.amounts.push((amount, unit)),
+        }
+    }
+}
+
+struct Line {
+    name: String,
+    used: Option<f64>,
+    /// `None` when the amounts can't be counted in packs
+    packs: Option<u32>,
+    pack_price: Option<f64>,
+    in_pantry: bool,
+    problem: Option<String>,
+}
+
+pub fn cost(db_path: &str, file: &Path, policy: OptionPolicy) -> Result<()> {
+    let root = recipes::recipes_root(db_path);
+    let resolver = NameResolver::load(db_path)?;
+    let pantry: Vec<String> = pantry::load(&pantry::pantry_path(db_path))?
+        .into_iter()
+        .map(|item| names::clean_name(&item.name))
+        .collect();
+
+    // Group by db entry so "onion" and "onions" across recipes share packs
+    let mut needs: BTreeMap<String, Need> = BTreeMap::new();
+    for ingredient in recipes::expand_ingredients(&root, file)? {
+        let key = resolver.canonical_key(&ingredient.name);
+        let need = needs.entry(key).or_default();
+        if need.name.is_empty() {
+            need.name = ingredient.name.to_lowercase();
+        }
+
+        let measured = ingredient
+            .amount
+            .and_then(|amount| price::convert(amount, ingredient.unit.as_deref().unwrap_or_default()));
+        match measured {
+            Some((amount, unit)) => need.add(amount, unit),
+            None => need.unmeasured = true,
+        }
+    }
+
+    let lines: Vec<Line> = needs
+        .iter()
+        .map(|(key, need)| {
+            let in_pantry = pantry.contains(key) || pantry.contains(&names::clean_name(&need.name));
+            price_need(&resolver, key, need, in_pantry, policy)
+        })
+        .collect();
+
+    println!("💶 Cost of {}\n", file.display());
+    println!("   {:<28} {:>9}   To buy", "Ingredient", "Used");
+
+    let mut used_total = 0.0;
+    let mut buy_total = 0.0;
+    let mut not_costed: Vec<String> = Vec::new();
+
+    for line in &lines {
+        let used = match line.used {
+            Some(used) => {
+                used_total += used;
+                format!("€{:.2}", used)
+            }
+            None => "?".to_string(),
+        };
+        let to_buy = match (line.in_pantry, line.pack_price, line.packs) {
+            (true, _, _) => "in pantry".to_string(),
+            (false, Some(pack_price), Some(packs)) => {
+                let total = pack_price * packs as f64;
+                buy_total += total;
+                format!("{} × €{:.2} = €{:.2}", packs, pack_price, total)
+            }
+            (false, _, _) => "?".to_string(),
+        };
+        println!("   {:<28} {:>9}   {}", line.name, used, to_buy);
+
+        // Pantry staples aren't bought, so a missing price doesn't matter
+        if let Some(problem) = &line.problem
+            && !line.in_pantry
+        {
+            not_costed.push(format!("{} ({})", line.name, problem));
+        }
+    }
+
+    println!("\n🧮 Ingredients used: €{:.2}", used_total);
+    println!("🛒 Packs to buy:     €{:.2}", buy_total);
+
+    if !not_costed.is_empty() {
+        println!("\n\x1b[33m⚠️  Not fully costed ({}), totals are a lower bound:\x1b[0m", not_costed.len());
+        for line in not_costed {
+            println!("   - {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+fn price_need(resolver: &NameResolver, key: &str, need: &Need, in_pantry: bool, policy: OptionPolicy) -> Line {
+    let mut line = Line {
+        name: need.name.clone(),
+        used: None,
+        packs: None,
+        pack_price: None,
+        in_pantry,
+        problem: None,
+    };
+
+    let data = resolver
+        .has_entry(key)
+        .then(|| ShoppingData::load(&resolver.shopping_yaml_path(key)).ok())
+        .flatten();
+    let Some(option) = data.as_ref().and_then(|data| preferred_option(data, policy)) else {
+        line.problem = Some(if data.is_some() { "no priced option" } else { "no db entry" }.to_string());
+        return line;
+    };
+
+    let unit_price = price::unit_price(option);
+    let pack_price = price::pack_price(option);
+    let pack = option.quantity.as_deref().and_then(price::pack_amount);
+    line.pack_price = pack_price;
+
+    // Price per unit of whatever the recipe measured, from the shelf label or the pack
+    let price_per = |unit: PriceUnit| -> Option<f64> {
+        if let Some(unit_price) = unit_price
+            && unit_price.unit == unit
+        {
+            return Some(unit_price.euros);
+        }
+        let (pack_amount, pack_unit) = pack?;
+        (pack_unit == unit).then_some(pack_price? / pack_amount)
+    };
+
+    let mut used = 0.0;
+    let mut unpriced = need.unmeasured;
+    for (amount, unit) in &need.amounts {
+        match price_per(*unit) {
+            Some(per) => used += amount * per,
+            None => unpriced = true,
+        }
+    }
+    line.used = (!need.amounts.is_empty() && used > 0.0).then_some(used);
+    if unpriced {
+        line.problem = Some("some amounts can't be measured against the pack".to_string());
+    }
+
+    // Whole packs to cover the measured amounts; at least one whenever it's used.
+    // Amounts in another unit than the pack (or a pack of unknown size) can't be counted.
+    let in_packs: Option<f64> = match pack {
+        _ if need.amounts.is_empty() => Some(0.0),
+        Some((pack_amount, pack_unit)) => need.amounts
+            .iter()
+            .map(|(amount, unit)| (*unit == pack_unit).then_some(amount / pack_amount))
+            .sum(),
+        None => None,
+    };
+    line.packs = match (in_pantry, in_packs) {
+        (true, _) => Some(0),
+        (false, Some(packs)) => Some((packs.ceil() as u32).max(1)),
+        (false, None) => {
+            line.problem = Some("not priced: the amounts can't be counted in packs".to_string());
+            None
+        }
+    };
+
+    line
+}
+
+/// The option `shop` would try first
+fn preferred_option(data: &ShoppingData, policy: OptionPolicy) -> Option<&ProductOption> {
+    let mut live: Vec<&ProductOption> = data
+        .sorted_options()
+        .into_iter()
+        .map(|(_, option)| option)
+        .filter(|option| !option.url.is_empty() && option.dead.is_none() && price::pack_price(option).is_some())
+        .collect();
+
+    if policy == OptionPolicy::Cheapest {
+        price::sort_cheapest_first(&mut live, |option| price::unit_price(option), |option| price::pack_price(option));
+    }
+    live.first().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A db with one entry, `milk`, holding these options
+    fn db_with(test: &str, options: &str) -> (std::path::PathBuf, NameResolver) {
+        let db = std::env::temp_dir().join(format!("shop-automation-cost-{}-{}", test, std::process::id()));
+        fs::create_dir_all(db.join("milk")).unwrap();
+        fs::write(db.join("milk").join("shopping.yml"), format!("supervalu:\n{}", options)).unwrap();
+        let resolver = NameResolver::load(db.to_str().unwrap()).unwrap();
+        (db, resolver)
+    }
+
+    const OPTIONS: &str = "  opt_1:\n    name: Milk 2L\n    url: https://shop.supervalu.ie/sm/delivery/rsid/5550/product/milk-2l-id-1001\n    price: €2.20\n    price_per_unit: €1.10/l\n    quantity: 2 l\n  opt_2:\n    name: Milk 1L\n    url: https://shop.supervalu.ie/sm/delivery/rsid/5550/product/milk-1l-id-1002\n    price: €1.00\n    price_per_unit: €1.00/l\n    quantity: 1 l\n";
+
+    fn need(amounts: &[(f64, PriceUnit)]) -> Need {
+        Need { name: "milk".to_string(), amounts: amounts.to_vec(), unmeasured: false }
+    }
+
+    #[test]
+    fn prices_what_is_used_and_the_packs_to_buy() {
+        let (db, resolver) = db_with("packs", OPTIONS);
+
+        let line = price_need(&resolver, "milk", &need(&[(3.0, PriceUnit::Litre)]), false, OptionPolicy::InOrder);
+        assert!((line.used.unwrap() - 3.3).abs() < 1e-9);
+        assert_eq!((line.packs, line.pack_price), (Some(2), Some(2.2)));
+        assert!(line.problem.is_none());
+
+        // Cheapest goes by €/l: the 1L carton
+        let line = price_need(&resolver, "milk", &need(&[(3.0, PriceUnit::Litre)]), false, OptionPolicy::Cheapest);
+        assert_eq!((line.packs, line.pack_price), (Some(3), Some(1.0)));
+
+        let line = price_need(&resolver, "milk", &need(&[(3.0, PriceUnit::Litre)]), true, OptionPolicy::InOrder);
+        assert_eq!(line.packs, Some(0));
+        fs::remove_dir_all(db).unwrap();
+    }
+
+    #[test]
+    fn leaves_amounts_in_several_units_unpriced() {
+        let (db, resolver) = db_with("units", OPTIONS);
+
+        let line = price_need(&resolver, "milk", &need(&[(0.5, PriceUnit::Litre), (2.0, PriceUnit::Each)]), false, OptionPolicy::InOrder);
+        assert_eq!(line.packs, None);
+        assert!(line.problem.unwrap().starts_with("not priced"));
+
+        let line = price_need(&resolver, "bread", &need(&[(1.0, PriceUnit::Each)]), false, OptionPolicy::InOrder);
+        assert_eq!(line.problem.as_deref(), Some("no db entry"));
+        fs::remove_dir_all(db).unwrap();
+    }
+}
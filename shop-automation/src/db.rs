@@ -0,0 +1,626 @@
+use anyhow::{Context, Result, bail};
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use crate::models::{ProductOption, ShoppingData, option_index};
+use crate::names::{self, MatchKind, NameResolver};
+use crate::pantry;
+use crate::profile::Profile;
+use crate::recipes::{self, Recipe};
+use crate::scraper::{self, UrlStatus};
+
+/// Accepts `opt_3` or just `3`, and returns the key as `db show` prints it
+fn parse_option_ref(option: &str) -> Result<String> {
+    let index = option_index(option);
+    if index != usize::MAX && index > 0 {
+        return Ok(format!("opt_{}", index));
+    }
+    match option.parse::<usize>() {
+        Ok(index) if index > 0 => Ok(format!("opt_{}", index)),
+        _ => bail!("Invalid option '{}'. Use opt_N or N, e.g. opt_3", option),
+    }
+}
+
+/// Position of an option key among the sorted options. Hand-edited files
+/// can skip numbers (opt_1, opt_3), so this goes by key, not by count.
+fn option_position(data: &ShoppingData, item: &str, option_key: &str) -> Result<usize> {
+    let options = data.sorted_options();
+    match options.iter().position(|(key, _)| key.as_str() == option_key) {
+        Some(position) => Ok(position),
+        None => {
+            let keys: Vec<&str> = options.iter().map(|(key, _)| key.as_str()).collect();
+            bail!("{} has no {} (options: {})", item, option_key, keys.join(", "))
+        }
+    }
+}
+
+/// Saving re-serializes shopping.yml, which loses any comments in it
+fn warn_dropped_comments(path: &Path) {
+    let has_comments = fs::read_to_string(path)
+        .is_ok_and(|content| content.lines().any(|line| line.trim_start().starts_with('#')));
+    if has_comments {
+        println!("\x1b[33m⚠ Comments in {:?} are dropped when it's saved\x1b[0m", path);
+    }
+}
+
+fn resolve_existing(resolver: &NameResolver, item: &str) -> Result<String> {
+    match resolver.resolve(item) {
+        Some(resolved) => {
+            if resolved.kind != MatchKind::Exact {
+                println!("🔗 {} resolved to '{}' ({:?} match)", item, resolved.key, resolved.kind);
+            }
+            Ok(resolved.key)
+        }
+        None => bail!("No db entry found for '{}'", item),
+    }
+}
+
+fn print_option(key: &str, option: &ProductOption) {
+    println!("  {}: {}", key, if option.name.is_empty() { "(empty)" } else { &option.name });
+    println!("      price: {} ({})", option.price, option.price_per_unit);
+    if let Some(quantity) = &option.quantity {
+        println!("      quantity: {}", quantity);
+    }
+    println!("      url: {}", if option.url.is_empty() { "(none)" } else { &option.url });
+    for (field, value) in &option.extra {
+        let value = serde_yaml::to_string(value).unwrap_or_default();
+        println!("      {}: {}", field, value.trim());
+    }
+}
+
+pub fn list(db_path: &str) -> Result<()> {
+    let resolver = NameResolver::load(db_path)?;
+
+    for entry in resolver.entries() {
+        let path = resolver.shopping_yaml_path(entry);
+        if !path.exists() {
+            continue;
+        }
+
+        match ShoppingData::load(&path) {
+            Ok(data) if data.is_empty_template() => {
+                println!("\x1b[31m{:<32} empty template\x1b[0m", entry);
+            }
+            Ok(data) => {
+                let options = data.sorted_options();
+                let (_, primary) = options[0];
+                println!("{:<32} {} option(s), primary: {} - {}", entry, options.len(), primary.name, primary.price);
+            }
+            Err(e) => println!("\x1b[31m{:<32} {}\x1b[0m", entry, e),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn show(db_path: &str, item: &str) -> Result<()> {
+    let resolver = NameResolver::load(db_path)?;
+    let key = resolve_existing(&resolver, item)?;
+    let path = resolver.shopping_yaml_path(&key);
+    let data = ShoppingData::load(&path)?;
+
+    println!("📦 {} ({:?})", key, path);
+    for (option_key, option) in data.sorted_options() {
+        print_option(option_key, option);
+    }
+
+    Ok(())
+}
+
+pub fn promote(db_path: &str, item: &str, option: &str) -> Result<()> {
+    let option_key = parse_option_ref(option)?;
+    let resolver = NameResolver::load(db_path)?;
+    let key = resolve_existing(&resolver, item)?;
+    let path = resolver.shopping_yaml_path(&key);
+    let mut data = ShoppingData::load(&path)?;
+    let position = option_position(&data, &key, &option_key)?;
+
+    // The rest keep their relative order behind the new primary
+    let mut options = data.take_options();
+    let promoted = options.remove(position);
+    println!("⬆️  {}: {} '{}' is now opt_1", key, option_key, promoted.name);
+    options.insert(0, promoted);
+
+    warn_dropped_comments(&path);
+    data.set_options(options);
+    data.save(&path)?;
+
+    Ok(())
+}
+
+pub fn remove_option(db_path: &str, item: &str, option: &str) -> Result<()> {
+    let option_key = parse_option_ref(option)?;
+    let resolver = NameResolver::load(db_path)?;
+    let key = resolve_existing(&resolver, item)?;
+    let path = resolver.shopping_yaml_path(&key);
+    let mut data = ShoppingData::load(&path)?;
+    let position = option_position(&data, &key, &option_key)?;
+
+    let mut options = data.take_options();
+    let removed = options.remove(position);
+    println!("🗑  {}: removed {} '{}'", key, option_key, removed.name);
+    if options.is_empty() {
+        println!("\x1b[33m⚠ {} has no options left\x1b[0m", key);
+    }
+
+    warn_dropped_comments(&path);
+    data.set_options(options);
+    data.save(&path)?;
+
+    Ok(())
+}
+
+pub async fn add(db_path: &str, item: &str, url: &str, visible: bool) -> Result<()> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        bail!("Invalid URL '{}': expected a full product URL", url);
+    }
+
+    let resolver = NameResolver::load(db_path)?;
+    if let Some(resolved) = resolver.resolve(item)
+        && resolved.kind != MatchKind::Exact
+    {
+        println!("🔗 {} resolved to '{}' ({:?} match)", item, resolved.key, resolved.kind);
+    }
+    let key = resolver.canonical_key(item);
+    let path = resolver.shopping_yaml_path(&key);
+
+    let mut data = if path.exists() {
+        ShoppingData::load(&path)?
+    } else {
+        let dir = resolver.db_path().join(&key);
+        fs::create_dir_all(&dir)
+            .context(format!("Failed to create directory: {:?}", dir))?;
+        ShoppingData::new()
+    };
+
+    let product = scraper::scrape_product_page(url, visible).await?;
+
+    warn_dropped_comments(&path);
+    add_scraped(&mut data, &key, product, url);
+    data.save(&path)?;
+
+    Ok(())
+}
+
+/// Store a scraped product as the last option, or refresh the option that has its URL
+fn add_scraped(data: &mut ShoppingData, key: &str, product: ProductOption, url: &str) {
+    // Drop the placeholder left by a failed scrape
+    let mut options: Vec<ProductOption> = data.take_options()
+        .into_iter()
+        .filter(|opt| !(opt.url.is_empty() && opt.name.is_empty()))
+        .collect();
+
+    if let Some(existing) = options.iter_mut().find(|opt| opt.url == product.url || opt.url == url) {
+        println!("🔄 {}: updated '{}' - {}", key, product.name, product.price);
+        let extra = std::mem::take(&mut existing.extra);
+        *existing = ProductOption { extra, ..product };
+    } else {
+        println!("➕ {}: added opt_{} '{}' - {}", key, options.len() + 1, product.name, product.price);
+        options.push(product);
+    }
+
+    data.set_options(options);
+}
+
+/// Lint the db against itself and against the recipes that use it
+pub fn check(db_path: &str, recipes_dir: &Path) -> Result<()> {
+    let resolver = NameResolver::load(db_path)?;
+    let mut problems = 0;
+
+    let mut empty_templates = Vec::new();
+    let mut blank_urls = Vec::new();
+    let mut bad_urls = Vec::new();
+    let mut urls: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for entry in resolver.entries() {
+        let path = resolver.shopping_yaml_path(entry);
+        if !path.exists() {
+            continue;
+        }
+        let data = match ShoppingData::load(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("\x1b[31m✗ {}: {:#}\x1b[0m", entry, e);
+                problems += 1;
+                continue;
+            }
+        };
+
+        if data.is_empty_template() {
+            empty_templates.push(entry.clone());
+            continue;
+        }
+
+        for (key, option) in data.sorted_options() {
+            let label = format!("{}.{}", entry, key);
+            if option.url.trim().is_empty() {
+                blank_urls.push(label);
+            } else if !scraper::is_product_url(&option.url) {
+                bad_urls.push(format!("{} ({})", label, option.url));
+            } else {
+                urls.entry(option.url.clone()).or_default().push(label);
+            }
+        }
+    }
+
+    // Same product stored under different items; alternatives within one item are fine
+    let duplicates: Vec<(String, Vec<String>)> = urls
+        .into_iter()
+        .filter(|(_, labels)| {
+            let items: BTreeSet<&str> = labels.iter().filter_map(|l| l.split('.').next()).collect();
+            items.len() > 1
+        })
+        .collect();
+
+    // Map every recipe ingredient onto the db
+    let pantry: Vec<String> = pantry::load(&pantry::pantry_path(db_path))?
+        .into_iter()
+        .map(|item| names::clean_name(&item.name))
+        .collect();
+    let mut referenced: BTreeSet<String> = BTreeSet::new();
+    let mut missing: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for file in recipes::find_recipe_files(recipes_dir) {
+        let recipe = match Recipe::load(&file) {
+            Ok(recipe) => recipe,
+            Err(e) => {
+                println!("\x1b[33m⚠ {:#}\x1b[0m", e);
+                continue;
+            }
+        };
+        let source = file.strip_prefix(recipes_dir).unwrap_or(&file).display().to_string();
+
+        for ingredient in recipe.ingredients.iter().filter(|i| !i.is_recipe_reference()) {
+            match resolver.resolve(&ingredient.name) {
+                Some(resolved) if resolver.shopping_yaml_path(&resolved.key).exists() => {
+                    referenced.insert(resolved.key);
+                }
+                _ if pantry.contains(&names::clean_name(&ingredient.name)) => {}
+                _ => {
+                    missing
+                        .entry(ingredient.name.to_lowercase())
+                        .or_default()
+                        .insert(source.clone());
+                }
+            }
+        }
+    }
+
+    let unreferenced: Vec<&String> = resolver
+        .entries()
+        .iter()
+        .filter(|entry| !referenced.contains(*entry))
+        .collect();
+
+    let mut report = |title: &str, lines: Vec<String>| {
+        if lines.is_empty() {
+            return;
+        }
+        problems += lines.len();
+        println!("\n\x1b[33m{} ({})\x1b[0m", title, lines.len());
+        for line in lines {
+            println!("   - {}", line);
+        }
+    };
+
+    report("Empty shopping.yml templates (failed scrapes)", empty_templates);
+    report("Options with blank URLs", blank_urls);
+    report("URLs that are not SuperValu product pages", bad_urls);
+    report(
+        "URLs stored under more than one item",
+        duplicates
+            .into_iter()
+            .map(|(url, labels)| format!("{} ({})", labels.join(", "), url))
+            .collect(),
+    );
+    report(
+        "Recipe ingredients with no db entry",
+        missing
+            .into_iter()
+            .map(|(name, files)| {
+                let files: Vec<String> = files.into_iter().collect();
+                format!("{} (used in {})", name, files.join(", "))
+            })
+            .collect(),
+    );
+
+    // Staples bought outside any recipe live here too, so these are only listed
+    if !unreferenced.is_empty() {
+        println!("\nℹ️  Directories not referenced by any recipe ({})", unreferenced.len());
+        for entry in unreferenced {
+            println!("   - {}", entry);
+        }
+    }
+
+    if problems > 0 {
+        bail!("{} problem(s) found in {}", problems, db_path);
+    }
+    println!("✅ No problems found in {}", db_path);
+
+    Ok(())
+}
+
+struct VerifyJob {
+    entry: String,
+    option_key: String,
+    url: String,
+}
+
+/// Visit every stored URL, mark delisted options as dead and optionally find replacements
+pub async fn verify(db_path: &str, profile: &Profile, items: &[String], concurrency: usize, rescrape: bool, visible: bool) -> Result<()> {
+    let resolver = NameResolver::load(db_path)?;
+
+    let entries: Vec<String> = if items.is_empty() {
+        resolver.entries().to_vec()
+    } else {
+        items
+            .iter()
+            .map(|item| resolve_existing(&resolver, item))
+            .collect::<Result<_>>()?
+    };
+
+    let mut jobs = Vec::new();
+    for entry in &entries {
+        let path = resolver.shopping_yaml_path(entry);
+        let Ok(data) = ShoppingData::load(&path) else {
+            continue;
+        };
+        for (option_key, option) in data.sorted_options() {
+            if !option.url.is_empty() {
+                jobs.push(VerifyJob {
+                    entry: entry.clone(),
+                    option_key: option_key.clone(),
+                    url: option.url.clone(),
+                });
+            }
+        }
+    }
+
+    if jobs.is_empty() {
+        println!("No stored URLs to verify");
+        return Ok(());
+    }
+
+    println!("🔍 Verifying {} URLs ({} at a time)...", jobs.len(), concurrency.max(1));
+    let browser = scraper::launch_browser(visible)?;
+
+    // headless_chrome is blocking, so each check runs on the blocking pool
+    // and buffer_unordered bounds how many tabs are open at once
+    let total = jobs.len();
+    let mut checks = stream::iter(jobs)
+        .map(|job| {
+            let browser = browser.clone();
+            tokio::task::spawn_blocking(move || {
+                let status = scraper::check_product_url(&browser, &job.url);
+                (job, status)
+            })
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut results: BTreeMap<String, Vec<(String, UrlStatus)>> = BTreeMap::new();
+    let mut done = 0;
+    let mut errors = 0;
+    while let Some(joined) = checks.next().await {
+        let (job, status) = joined.context("URL check task panicked")?;
+        done += 1;
+        match status {
+            Ok(status) => {
+                match &status {
+                    UrlStatus::Available => println!("   [{}/{}] ✅ {}.{}", done, total, job.entry, job.option_key),
+                    UrlStatus::OutOfStock => println!("   [{}/{}] ⚠️  {}.{} out of stock", done, total, job.entry, job.option_key),
+                    UrlStatus::NotFound => println!("\x1b[31m   [{}/{}] ✗ {}.{} not found\x1b[0m", done, total, job.entry, job.option_key),
+                    UrlStatus::RedirectedToSearch(to) => println!("\x1b[31m   [{}/{}] ✗ {}.{} redirected to {}\x1b[0m", done, total, job.entry, job.option_key, to),
+                    UrlStatus::Unknown(reason) => println!("\x1b[33m   [{}/{}] ? {}.{} couldn't tell: {}\x1b[0m", done, total, job.entry, job.option_key, reason),
+                }
+                results.entry(job.entry).or_default().push((job.option_key, status));
+            }
+            Err(e) => {
+                errors += 1;
+                println!("   [{}/{}] ❌ {}.{}: {:#}", done, total, job.entry, job.option_key, e);
+            }
+        }
+    }
+
+    // Write the marks back, one file per item
+    let mut dead_count = 0;
+    let mut unknown_count = 0;
+    let mut exhausted = Vec::new();
+    for (entry, statuses) in results {
+        let path = resolver.shopping_yaml_path(&entry);
+        let mut data = ShoppingData::load(&path)?;
+        let mut changed = false;
+
+        for (option_key, status) in statuses {
+            let Some(option) = data.supervalu.get_mut(&option_key) else {
+                continue;
+            };
+            let mark = match &status {
+                UrlStatus::NotFound => Some("not found".to_string()),
+                UrlStatus::RedirectedToSearch(_) => Some("redirected to search".to_string()),
+                UrlStatus::Available | UrlStatus::OutOfStock => None,
+                // Keep whatever the last conclusive check found
+                UrlStatus::Unknown(_) => {
+                    unknown_count += 1;
+                    continue;
+                }
+            };
+            if status.is_dead() {
+                dead_count += 1;
+            }
+            if option.dead != mark {
+                option.dead = mark;
+                changed = true;
+            }
+        }
+
+        if changed {
+            warn_dropped_comments(&path);
+            data.save(&path)?;
+        }
+        if data.supervalu.values().all(|opt| opt.dead.is_some() || opt.url.is_empty()) {
+            exhausted.push(entry);
+        }
+    }
+
+    println!("\n📊 Checked {} URLs: {} dead, {} unknown, {} errors", total, dead_count, unknown_count, errors);
+
+    if exhausted.is_empty() {
+        return Ok(());
+    }
+
+    if !rescrape {
+        println!("\n\x1b[33mItems with no live options left (use --rescrape to search for replacements):\x1b[0m");
+        for entry in &exhausted {
+            println!("   - {}", entry);
+        }
+        return Ok(());
+    }
+
+    for entry in exhausted {
+        let search = entry.replace('_', " ");
+        println!("\n🔄 Searching replacements for {}...", entry);
+        let products = match scraper::search_products(&search, &profile.store, visible) {
+            Ok(products) => products,
+            Err(e) => {
+                println!("   ❌ Failed to search for {}: {:#}", entry, e);
+                continue;
+            }
+        };
+
+        let path = resolver.shopping_yaml_path(&entry);
+        let mut data = ShoppingData::load(&path)?;
+        let mut options = data.take_options();
+        let mut added = 0;
+        for product in products {
+            if options.iter().any(|opt| opt.url == product.url) {
+                continue;
+            }
+            println!("   ➕ {} - {}", product.name, product.price);
+            options.push(product);
+            added += 1;
+        }
+
+        if added == 0 {
+            println!("\x1b[31m   ⚠ No new products found for {}\x1b[0m", entry);
+            continue;
+        }
+
+        // Live options first so opt_1 is something that can actually be bought
+        options.sort_by_key(|opt| opt.dead.is_some());
+        data.set_options(options);
+        warn_dropped_comments(&path);
+        data.save(&path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(name: &str, id: u32) -> String {
+        format!(
+            "  opt_{id}:\n    name: {name}\n    url: https://shop.supervalu.ie/sm/delivery/rsid/5550/product/{name}-id-{id}\n    price: €1.00\n    price_per_unit: €1.00/kg\n"
+        )
+    }
+
+    /// A db with one `rice` entry whose options skip a number, like a hand-edited file
+    fn db_with_rice(test: &str) -> (std::path::PathBuf, String) {
+        let db = std::env::temp_dir().join(format!("shop-automation-db-{}-{}", test, std::process::id()));
+        fs::create_dir_all(db.join("rice")).unwrap();
+        let options = [option("basmati", 1), option("jasmine", 3), option("brown", 4)].concat();
+        fs::write(db.join("rice").join("shopping.yml"), format!("supervalu:\n{}", options)).unwrap();
+        let db_path = db.to_string_lossy().to_string();
+        (db, db_path)
+    }
+
+    fn names_in_order(db: &Path) -> Vec<String> {
+        let data = ShoppingData::load(&db.join("rice").join("shopping.yml")).unwrap();
+        data.sorted_options().iter().map(|(key, opt)| format!("{}={}", key, opt.name)).collect()
+    }
+
+    fn product(name: &str, url: &str) -> ProductOption {
+        ProductOption {
+            name: name.to_string(),
+            url: url.to_string(),
+            price: "€2.00".to_string(),
+            price_per_unit: "€2.00/kg".to_string(),
+            quantity: None,
+            dead: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn accepts_option_keys_or_numbers() {
+        assert_eq!(parse_option_ref("opt_3").unwrap(), "opt_3");
+        assert_eq!(parse_option_ref("3").unwrap(), "opt_3");
+        assert!(parse_option_ref("0").is_err());
+        assert!(parse_option_ref("third").is_err());
+    }
+
+    #[test]
+    fn lists_entries_including_empty_templates() {
+        let (db, db_path) = db_with_rice("list");
+        fs::create_dir_all(db.join("salt")).unwrap();
+        fs::write(db.join("salt").join("shopping.yml"), "supervalu:\n  opt_1:\n    name: ''\n    url: ''\n    price: ''\n    price_per_unit: ''\n").unwrap();
+
+        assert!(list(&db_path).is_ok());
+        fs::remove_dir_all(&db).unwrap();
+    }
+
+    #[test]
+    fn promote_moves_the_option_first_and_renumbers() {
+        let (db, db_path) = db_with_rice("promote");
+
+        promote(&db_path, "rice", "opt_3").unwrap();
+        assert_eq!(names_in_order(&db), ["opt_1=jasmine", "opt_2=basmati", "opt_3=brown"]);
+
+        // opt_3 no longer exists once the options are renumbered
+        promote(&db_path, "rice", "4").unwrap_err();
+        fs::remove_dir_all(&db).unwrap();
+    }
+
+    #[test]
+    fn remove_option_goes_by_key_not_by_count() {
+        let (db, db_path) = db_with_rice("remove");
+
+        remove_option(&db_path, "rice", "4").unwrap();
+        assert_eq!(names_in_order(&db), ["opt_1=basmati", "opt_2=jasmine"]);
+        assert!(remove_option(&db_path, "pasta", "1").is_err());
+        fs::remove_dir_all(&db).unwrap();
+    }
+
+    #[test]
+    fn add_appends_new_urls_and_refreshes_known_ones() {
+        let (db, _) = db_with_rice("add");
+        let path = db.join("rice").join("shopping.yml");
+        let mut data = ShoppingData::load(&path).unwrap();
+        data.supervalu.get_mut("opt_3").unwrap().extra.insert("note".to_string(), "for curry".into());
+
+        let url = "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/arborio-id-9";
+        add_scraped(&mut data, "rice", product("arborio", url), url);
+        let known = data.supervalu["opt_2"].url.clone();
+        add_scraped(&mut data, "rice", product("jasmine 1kg", &known), &known);
+
+        let options = data.sorted_options();
+        let names: Vec<&str> = options.iter().map(|(_, opt)| opt.name.as_str()).collect();
+        assert_eq!(names, ["basmati", "jasmine 1kg", "brown", "arborio"]);
+        assert_eq!(options[1].1.price, "€2.00");
+        assert!(options[1].1.extra.contains_key("note"));
+        fs::remove_dir_all(&db).unwrap();
+    }
+
+    #[test]
+    fn add_replaces_the_placeholder_of_a_failed_scrape() {
+        let mut data = ShoppingData::new();
+        data.add_option(1, product("", ""));
+        let url = "https://shop.supervalu.ie/sm/delivery/rsid/5550/product/salt-id-7";
+
+        add_scraped(&mut data, "salt", product("salt", url), url);
+        assert_eq!(data.supervalu.len(), 1);
+        assert_eq!(data.supervalu["opt_1"].name, "salt");
+    }
+}
@@ -0,0 +1,378 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Weekday};
+use headless_chrome::Tab;
+use std::time::Duration;
+use crate::calendar::parse_time;
+use crate::price;
+use crate::settings::DeliveryPrefs;
+
+// Delivery slot booking for `shop`: open the slot picker, read every slot on
+// it, and click the cheapest one that fits the `delivery:` preferences in
+// `config/shop-automation.yml`, earliest first on a tie.
+
+/// Seconds to wait for a booked slot to show as reserved
+const RESERVATION_CHECKS: u32 = 5;
+
+/// One slot as shown on the picker
+#[derive(Debug)]
+pub struct Slot {
+    /// Position among the slot elements, for clicking it
+    index: usize,
+    /// "Saturday 12 July 4:00pm - 5:00pm €3.00", for the report
+    pub label: String,
+    /// The element's own text, to find it again after the picker re-renders
+    text: String,
+    pub day: Option<Weekday>,
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    /// `Some(0.0)` for free delivery
+    pub fee: Option<f64>,
+    pub available: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct RawSlot {
+    text: String,
+    label: String,
+    day: String,
+    disabled: bool,
+}
+
+/// "Saturday 12 July", "Sat", "Tomorrow", "2025-07-12" -> the weekday
+fn parse_day(text: &str) -> Option<Weekday> {
+    if let Some(date) = text.get(..10).and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()) {
+        return Some(date.weekday());
+    }
+    let today = Local::now().date_naive();
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphabetic())
+        .find_map(|word| match word {
+            "today" => Some(today.weekday()),
+            "tomorrow" => Some(today.weekday().succ()),
+            // chrono takes "sat" and "saturday" but also "s", so insist on three letters
+            _ if word.len() >= 3 => word.parse().ok(),
+            _ => None,
+        })
+}
+
+/// "4:00pm - 5:00pm", "16:00–17:00", "4pm to 5pm" -> start and end
+fn parse_window(text: &str) -> (Option<NaiveTime>, Option<NaiveTime>) {
+    let lower = text.to_lowercase();
+    let Some((before, after)) = lower
+        .split_once(" to ")
+        .or_else(|| lower.split_once('–'))
+        .or_else(|| lower.split_once('-'))
+    else {
+        return (parse_time(&lower), None);
+    };
+    // The start is right before the separator; earlier words may be a date
+    let words: Vec<&str> = before.split_whitespace().collect();
+    let start = parse_time(&words[words.len().saturating_sub(2)..].join(" "));
+    (start, parse_time(after))
+}
+
+fn parse_slot(index: usize, raw: RawSlot) -> Slot {
+    let label = [raw.day.as_str(), raw.label.as_str(), raw.text.as_str()]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let lower = label.to_lowercase();
+    let (start, end) = parse_window(if raw.text.is_empty() { &raw.label } else { &raw.text });
+    let fee = if lower.contains("free") { Some(0.0) } else { price::parse_euros(&label) };
+    let day = parse_day(&raw.day).or_else(|| parse_day(&raw.label));
+
+    Slot {
+        index,
+        text: raw.text,
+        day,
+        start,
+        end,
+        fee,
+        available: !raw.disabled,
+        label,
+    }
+}
+
+/// Why a slot doesn't fit, or `None` when it does
+fn rejection(slot: &Slot, prefs: &DeliveryPrefs) -> Option<String> {
+    if !slot.available {
+        return Some("unavailable".to_string());
+    }
+    if !prefs.days.is_empty() {
+        let wanted: Vec<Weekday> = prefs.days.iter().filter_map(|day| parse_day(day)).collect();
+        if !slot.day.is_some_and(|day| wanted.contains(&day)) {
+            return Some("wrong day".to_string());
+        }
+    }
+    if let Some(from) = prefs.from.as_deref().and_then(parse_time)
+        && slot.start.is_none_or(|start| start < from)
+    {
+        return Some("too early".to_string());
+    }
+    if let Some(to) = prefs.to.as_deref().and_then(parse_time)
+        && slot.end.or(slot.start).is_none_or(|end| end > to)
+    {
+        return Some("too late".to_string());
+    }
+    if let Some(max_fee) = prefs.max_fee
+        && !slot.fee.is_some_and(|fee| fee <= max_fee + 0.005)
+    {
+        return Some(match slot.fee {
+            Some(fee) => format!("€{:.2} fee", fee),
+            None => "fee unknown".to_string(),
+        });
+    }
+    None
+}
+
+/// Cheapest slot that fits, earliest on a tie (slots are listed in time order).
+/// Slots whose fee couldn't be read come after every priced one.
+pub fn choose<'a>(slots: &'a [Slot], prefs: &DeliveryPrefs) -> Option<&'a Slot> {
+    slots
+        .iter()
+        .filter(|slot| rejection(slot, prefs).is_none())
+        .min_by(|a, b| {
+            a.fee
+                .is_none()
+                .cmp(&b.fee.is_none())
+                .then(a.fee.unwrap_or_default().total_cmp(&b.fee.unwrap_or_default()))
+                .then(a.index.cmp(&b.index))
+        })
+}
+
+/// Open the slot picker and read what it offers
+pub fn read_slots(tab: &Tab) -> Result<Vec<Slot>> {
+    let open_js = r#"
+        (() => {
+            const pattern = /book a (delivery )?slot|choose a (delivery )?time|delivery slot|select a (time|slot)|change slot/i;
+            const buttons = document.querySelectorAll('button, a');
+            for (const btn of buttons) {
+                const text = (btn.textContent || '') + ' ' + (btn.getAttribute('aria-label') || '');
+                if (pattern.test(text)) {
+                    btn.click();
+                    return true;
+                }
+            }
+            return false;
+        })()
+    "#;
+    let opened = tab.evaluate(open_js, false)?;
+    if !matches!(opened.value, Some(serde_json::Value::Bool(true))) {
+        bail!("couldn't find the button that opens the delivery slots");
+    }
+    std::thread::sleep(Duration::from_secs(3));
+
+    // Slot elements are kept on `window` so `book` clicks the same element
+    let slots_js = r#"
+        (() => {
+            const selector = '[data-testid*="slot" i], [class*="TimeSlot"], [class*="timeslot" i], [class*="time-slot" i]';
+            const slots = Array.from(document.querySelectorAll(selector))
+                .filter(el => /\d/.test(el.textContent) && !el.querySelector(selector));
+            window.__shopAutomationSlots = slots;
+
+            const dayOf = (el) => {
+                const dated = el.closest('[data-date]');
+                if (dated) return dated.getAttribute('data-date');
+                for (let node = el; node; node = node.parentElement) {
+                    for (let prev = node.previousElementSibling; prev; prev = prev.previousElementSibling) {
+                        if (prev.matches('h2, h3, h4, [class*="day" i], [class*="date" i]')) {
+                            return prev.textContent.trim();
+                        }
+                    }
+                }
+                return '';
+            };
+
+            return JSON.stringify(slots.map(el => ({
+                text: el.textContent.trim().replace(/\s+/g, ' '),
+                label: el.getAttribute('aria-label') || '',
+                day: dayOf(el),
+                disabled: !!(el.disabled
+                    || el.getAttribute('aria-disabled') === 'true'
+                    || /unavailable|disabled|full/i.test(el.className)
+                    || /unavailable|fully booked|sold out/i.test(el.textContent))
+            })));
+        })()
+    "#;
+    let result = tab.evaluate(slots_js, false)?;
+    let raw: Vec<RawSlot> = match result.value.as_ref().and_then(|value| value.as_str()) {
+        Some(json) => serde_json::from_str(json)?,
+        None => Vec::new(),
+    };
+
+    Ok(raw.into_iter().enumerate().map(|(index, raw)| parse_slot(index, raw)).collect())
+}
+
+/// What the page shows after booking
+#[derive(serde::Deserialize)]
+struct Reservation {
+    /// The slot element is marked selected or reserved
+    selected: bool,
+    /// Short texts like "Your slot is reserved: Sat 12 July, 4pm - 5pm"
+    banners: Vec<String>,
+}
+
+impl Reservation {
+    /// The slot is marked, or a banner names its start time (and its day, if it has one)
+    fn shows(&self, slot: &Slot) -> bool {
+        self.selected
+            || self.banners.iter().any(|banner| {
+                let (start, _) = parse_window(banner);
+                start.is_some()
+                    && start == slot.start
+                    && parse_day(banner).is_none_or(|day| slot.day.is_none_or(|slot_day| slot_day == day))
+            })
+    }
+}
+
+fn read_reservation(tab: &Tab, slot: &Slot) -> Result<Reservation> {
+    let reservation_js = format!(r#"
+        (() => {{
+            const selector = '[data-testid*="slot" i], [class*="TimeSlot"], [class*="timeslot" i], [class*="time-slot" i]';
+            const norm = t => (t || '').trim().replace(/\s+/g, ' ');
+            const marked = el => el.getAttribute('aria-pressed') === 'true'
+                || el.getAttribute('aria-selected') === 'true'
+                || el.getAttribute('aria-checked') === 'true'
+                || /selected|reserved|booked|chosen/i.test(el.className)
+                || !!el.querySelector('[aria-checked="true"], [aria-selected="true"]');
+            const text = {};
+            const candidates = [(window.__shopAutomationSlots || [])[{}]]
+                .concat(Array.from(document.querySelectorAll(selector)).filter(el => norm(el.textContent) === text));
+            const selected = candidates.some(el => el && el.isConnected && marked(el));
+            const banners = Array.from(document.querySelectorAll('[role="status"], [role="alert"], header, section, div, p, span'))
+                .filter(el => el.children.length < 6)
+                .map(el => norm(el.textContent))
+                .filter(t => t.length < 160 && /reserved|booked|your (delivery )?slot/i.test(t));
+            return JSON.stringify({{ selected, banners }});
+        }})()
+    "#, slot.index, serde_json::to_string(&slot.text)?);
+    let result = tab.evaluate(&reservation_js, false)?;
+    let json = result.value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .context("the page didn't say whether the slot is reserved")?;
+    Ok(serde_json::from_str(json)?)
+}
+
+/// Click a slot read by `read_slots`, confirm the reservation if asked, and
+/// check the page shows it as reserved
+pub fn book(tab: &Tab, slot: &Slot) -> Result<()> {
+    let click_js = format!(r#"
+        (() => {{
+            const slot = (window.__shopAutomationSlots || [])[{}];
+            if (!slot) return false;
+            slot.click();
+            return true;
+        }})()
+    "#, slot.index);
+    let clicked = tab.evaluate(&click_js, false)?;
+    if !matches!(clicked.value, Some(serde_json::Value::Bool(true))) {
+        bail!("the slot disappeared from the page before it could be booked");
+    }
+    std::thread::sleep(Duration::from_secs(2));
+
+    let confirm_js = r#"
+        (() => {
+            for (const btn of document.querySelectorAll('button')) {
+                if (/^(confirm|reserve|book)( (slot|this slot|delivery))?$/i.test(btn.textContent.trim()) && !btn.disabled) {
+                    btn.click();
+                    return true;
+                }
+            }
+            return false;
+        })()
+    "#;
+    let confirmed = tab.evaluate(confirm_js, false)
+        .is_ok_and(|result| matches!(result.value, Some(serde_json::Value::Bool(true))));
+
+    // The reservation can take a moment to show
+    for _ in 0..RESERVATION_CHECKS {
+        std::thread::sleep(Duration::from_secs(1));
+        if read_reservation(tab, slot)?.shows(slot) {
+            return Ok(());
+        }
+    }
+    if confirmed {
+        bail!("confirmed {}, but the page doesn't show it as reserved", slot.label);
+    }
+    bail!("{} isn't shown as reserved and there was no confirm button", slot.label);
+}
+
+/// Read the slots, pick one and book it; the booked slot's label, if any
+pub fn book_preferred(tab: &Tab, prefs: &DeliveryPrefs) -> Result<Option<String>> {
+    println!("📅 Looking for a delivery slot...");
+    let slots = read_slots(tab)?;
+    if slots.is_empty() {
+        bail!("no delivery slots found on the page");
+    }
+
+    let Some(slot) = choose(&slots, prefs) else {
+        println!("   \x1b[33m⚠️  None of the {} slots fit the delivery preferences:\x1b[0m", slots.len());
+        for slot in slots.iter().filter(|slot| slot.available).take(10) {
+            println!("      - {} ({})", slot.label, rejection(slot, prefs).unwrap_or_default());
+        }
+        return Ok(None);
+    };
+
+    book(tab, slot)?;
+    println!("   ✅ Booked {}", slot.label);
+    Ok(Some(slot.label.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    fn slot(index: usize, fee: Option<f64>) -> Slot {
+        Slot {
+            index,
+            label: format!("slot {}", index),
+            text: String::new(),
+            day: Some(Weekday::Sat),
+            start: time(16, 0),
+            end: time(17, 0),
+            fee,
+            available: true,
+        }
+    }
+
+    #[test]
+    fn chooses_the_cheapest_slot_with_unknown_fees_last() {
+        let prefs = DeliveryPrefs::default();
+        let slots = [slot(0, None), slot(1, Some(3.0)), slot(2, Some(0.0)), slot(3, Some(0.0))];
+        assert_eq!(choose(&slots, &prefs).map(|s| s.index), Some(2));
+
+        let slots = [slot(0, None), slot(1, Some(4.5))];
+        assert_eq!(choose(&slots, &prefs).map(|s| s.index), Some(1));
+    }
+
+    #[test]
+    fn recognises_the_reservation_banner() {
+        let booked = slot(0, Some(3.0));
+        let reservation = |banner: &str| Reservation { selected: false, banners: vec![banner.to_string()] };
+        assert!(reservation("Your slot is reserved: Saturday 12 July, 4:00pm - 5:00pm").shows(&booked));
+        assert!(!reservation("Your slot is reserved: Sunday 13 July, 4:00pm - 5:00pm").shows(&booked));
+        assert!(!reservation("Book a slot").shows(&booked));
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(parse_day("Saturday 12 July"), Some(Weekday::Sat));
+        assert_eq!(parse_day("Sat"), Some(Weekday::Sat));
+        assert_eq!(parse_day("2025-07-12"), Some(Weekday::Sat));
+        assert_eq!(parse_day("12 July"), None);
+    }
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!(parse_window("4:00pm - 5:00pm"), (time(16, 0), time(17, 0)));
+        assert_eq!(parse_window("16:00–17:30"), (time(16, 0), time(17, 30)));
+        assert_eq!(parse_window("Saturday 12 July 4pm to 5pm"), (time(16, 0), time(17, 0)));
+    }
+}
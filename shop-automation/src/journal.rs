@@ -0,0 +1,397 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Duration, Local, NaiveDate};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::menu::Menu;
+use crate::pantry;
+use crate::recipes::{self, Ingredient};
+
+// Meal journals in `journal/YYYY-MM-DD.journal`, one per day of a menu:
+//
+//   # Meal Journal - July 09, 2025
+//
+//   ## Breakfast
+//   - [x] @./Breakfast/Shakshuka.cook{2}   <- planned and eaten
+//   - [-] @sourdough bread{2%slices}        <- planned, skipped (a leftover)
+//   - [ ] @kefir{200%ml}                    <- planned, not recorded yet
+//   - @banana{1}                            <- eaten, not planned
+//
+// Once a day is over, what was skipped goes back into the pantry and what
+// was eaten on top of the plan comes out of it. Dishes nobody marked are
+// left alone: an untouched journal says nothing about what was eaten.
+
+const MEALS: [&str; 4] = ["Breakfast", "Lunch", "Dinner", "Snacks"];
+
+/// Added to a journal's notes once its differences are in the pantry
+const PANTRY_UPDATED: &str = "- Pantry updated from this day";
+
+/// Journals sit in `journal/` at the recipes root
+pub fn journal_dir(db_path: &str) -> PathBuf {
+    recipes::recipes_root(db_path).join("journal")
+}
+
+fn journal_path(dir: &Path, date: NaiveDate) -> PathBuf {
+    dir.join(format!("{}.journal", date.format("%Y-%m-%d")))
+}
+
+fn render(date: NaiveDate, meals: &[(&str, Vec<String>)], notes: &[String]) -> String {
+    let mut text = format!("# Meal Journal - {}\n", date.format("%B %d, %Y"));
+    for (meal, dishes) in meals {
+        text.push_str(&format!("\n## {}\n", meal));
+        if dishes.is_empty() {
+            text.push_str("- \n");
+        }
+        for dish in dishes {
+            text.push_str(&format!("- [ ] {}\n", dish));
+        }
+    }
+    text.push_str("\n## Notes\n");
+    for note in notes {
+        text.push_str(&format!("- {}\n", note));
+    }
+    text
+}
+
+/// One journal per menu day, the first day on `start`
+pub fn create(menu_path: &Path, start: NaiveDate, dir: &Path, force: bool) -> Result<()> {
+    let menu = Menu::load(menu_path)?;
+    let Some(first_day) = menu.days().filter_map(|section| section.day).min() else {
+        bail!("{} has no ==Day N== sections", menu_path.display());
+    };
+    fs::create_dir_all(dir).context(format!("Failed to create {:?}", dir))?;
+    let plan = menu_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut written = 0;
+    for section in menu.days() {
+        let date = start + Duration::days((section.day.unwrap_or(first_day) - first_day) as i64);
+        let path = journal_path(dir, date);
+        if path.exists() && !force {
+            println!("⏭️  {} already exists (--force to overwrite)", path.display());
+            continue;
+        }
+
+        let meals: Vec<(&str, Vec<String>)> = MEALS
+            .iter()
+            .map(|&meal| {
+                let dishes = section
+                    .meal(meal)
+                    .map(|m| m.dishes.iter().map(|dish| dish.text.clone()).collect())
+                    .unwrap_or_default();
+                (meal, dishes)
+            })
+            .collect();
+        let mut notes = section.notes.clone();
+        notes.push(format!("Planned: {}, {}", plan, section.title));
+
+        fs::write(&path, render(date, &meals, &notes))
+            .context(format!("Failed to write {:?}", path))?;
+        println!("📔 {} ({})", path.display(), section.title);
+        written += 1;
+    }
+
+    println!("\n✅ Wrote {} journal(s). Tick off what you eat with `journal mark`.", written);
+    Ok(())
+}
+
+/// The box in front of a planned dish
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Unmarked,
+    Eaten,
+    Skipped,
+}
+
+impl Mark {
+    fn checkbox(self) -> &'static str {
+        match self {
+            Mark::Unmarked => "[ ]",
+            Mark::Eaten => "[x]",
+            Mark::Skipped => "[-]",
+        }
+    }
+}
+
+/// What a journal line records
+enum Entry<'a> {
+    Planned { mark: Mark, text: &'a str },
+    Unplanned(&'a str),
+}
+
+fn parse_entry(line: &str) -> Option<Entry<'_>> {
+    let item = line.trim().strip_prefix('-')?.trim();
+    let boxes = [("[x]", Mark::Eaten), ("[X]", Mark::Eaten), ("[-]", Mark::Skipped), ("[ ]", Mark::Unmarked)];
+    if let Some((text, mark)) = boxes.iter().find_map(|(checkbox, mark)| Some((item.strip_prefix(checkbox)?, *mark))) {
+        Some(Entry::Planned { mark, text: text.trim() })
+    } else if !item.is_empty() {
+        Some(Entry::Unplanned(item))
+    } else {
+        None
+    }
+}
+
+fn read_journal(dir: &Path, date: NaiveDate) -> Result<(PathBuf, Vec<String>)> {
+    let path = journal_path(dir, date);
+    let content = fs::read_to_string(&path)
+        .context(format!("No journal for {} at {:?} (create one with `journal create`)", date, path))?;
+    Ok((path, content.lines().map(str::to_string).collect()))
+}
+
+/// Lines of a `## Meal` section: (first line, end)
+fn meal_range(lines: &[String], meal: &str) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| {
+        line.trim()
+            .strip_prefix("## ")
+            .is_some_and(|heading| heading.trim().eq_ignore_ascii_case(meal))
+    })?;
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim().starts_with('#'))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    Some((start + 1, end))
+}
+
+/// Mark a meal's planned dishes as eaten or skipped, or only those containing `dish`
+pub fn mark(dir: &Path, date: NaiveDate, meal: &str, dish: Option<&str>, eaten: bool) -> Result<()> {
+    let (path, mut lines) = read_journal(dir, date)?;
+    let Some((start, end)) = meal_range(&lines, meal) else {
+        bail!("{} has no ## {} section", path.display(), meal);
+    };
+
+    let to = if eaten { Mark::Eaten } else { Mark::Skipped };
+    let mut changed = Vec::new();
+    for line in &mut lines[start..end] {
+        let Some(Entry::Planned { mark, text }) = parse_entry(line) else {
+            continue;
+        };
+        if mark == to || dish.is_some_and(|d| !text.to_lowercase().contains(&d.to_lowercase())) {
+            continue;
+        }
+        let text = text.to_string();
+        let indent = &line[..line.len() - line.trim_start().len()];
+        *line = format!("{}- {} {}", indent, to.checkbox(), text);
+        changed.push(text);
+    }
+
+    if changed.is_empty() {
+        println!("Nothing to change in {} on {}", meal, date);
+        return Ok(());
+    }
+    fs::write(&path, lines.join("\n") + "\n").context(format!("Failed to write {:?}", path))?;
+    let verb = if eaten { "✅ Eaten" } else { "↩️  Skipped" };
+    for text in changed {
+        println!("{}: {}", verb, text);
+    }
+    Ok(())
+}
+
+/// Log something eaten that wasn't on the plan, e.g. "@banana{1}"
+pub fn ate(dir: &Path, date: NaiveDate, meal: &str, text: &str) -> Result<()> {
+    let (path, mut lines) = read_journal(dir, date)?;
+    let Some((start, end)) = meal_range(&lines, meal) else {
+        bail!("{} has no ## {} section", path.display(), meal);
+    };
+
+    // Fill the empty "- " placeholder, or go after the last entry
+    let entry = format!("- {}", text);
+    match (start..end).find(|&i| lines[i].trim() == "-") {
+        Some(placeholder) => lines[placeholder] = entry,
+        None => {
+            let after = (start..end).rev().find(|&i| !lines[i].trim().is_empty()).map_or(start, |i| i + 1);
+            lines.insert(after, entry);
+        }
+    }
+
+    fs::write(&path, lines.join("\n") + "\n").context(format!("Failed to write {:?}", path))?;
+    println!("📝 Logged {} for {} on {}", text, meal, date);
+    Ok(())
+}
+
+/// Apply finished journals to the pantry: planned dishes marked skipped are
+/// leftovers, unplanned entries were eaten from stock. Unmarked dishes are
+/// left out. Without `files`, every journal before today that hasn't been
+/// applied yet.
+pub fn update_pantry(db_path: &str, dir: &Path, files: &[PathBuf]) -> Result<()> {
+    let root = recipes::recipes_root(db_path);
+    let pantry_path = pantry::pantry_path(db_path);
+    let today = Local::now().date_naive();
+
+    let files: Vec<PathBuf> = if files.is_empty() {
+        let mut found: Vec<PathBuf> = fs::read_dir(dir)
+            .context(format!("Failed to read journal directory {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "journal")
+                    && path
+                        .file_stem()
+                        .and_then(|stem| NaiveDate::parse_from_str(&stem.to_string_lossy(), "%Y-%m-%d").ok())
+                        .is_some_and(|date| date < today)
+            })
+            .collect();
+        found.sort();
+        found
+    } else {
+        files.to_vec()
+    };
+
+    let mut applied = 0;
+    for path in files {
+        let content = fs::read_to_string(&path).context(format!("Failed to read {:?}", path))?;
+        if content.lines().any(|line| line.trim() == PANTRY_UPDATED) {
+            continue;
+        }
+
+        let mut leftovers: Vec<Ingredient> = Vec::new();
+        let mut eaten: Vec<Ingredient> = Vec::new();
+        let mut untracked = Vec::new();
+        let mut unmarked = 0;
+        let mut meal = "";
+        for line in content.lines() {
+            if let Some(heading) = line.trim().strip_prefix("## ") {
+                meal = heading.trim();
+                continue;
+            }
+            if !MEALS.iter().any(|m| m.eq_ignore_ascii_case(meal)) {
+                continue;
+            }
+            let (text, into) = match parse_entry(line) {
+                Some(Entry::Planned { mark: Mark::Skipped, text }) => (text, &mut leftovers),
+                Some(Entry::Planned { mark: Mark::Unmarked, .. }) => {
+                    unmarked += 1;
+                    continue;
+                }
+                Some(Entry::Unplanned(text)) => (text, &mut eaten),
+                _ => continue,
+            };
+            let ingredients = recipes::parse_line_ingredients(text);
+            if ingredients.is_empty() {
+                untracked.push(text.to_string());
+            }
+            into.extend(recipes::expand(&root, &path, ingredients)?);
+        }
+
+        println!("📔 {}", path.display());
+        let changes = pantry::adjust(&pantry_path, &leftovers, &eaten)?;
+        for change in &changes {
+            println!("   {}", change);
+        }
+        if changes.is_empty() {
+            println!("   Ate as planned, pantry unchanged");
+        }
+        for text in untracked {
+            println!("   \x1b[33m⚠️  No @ingredients, not tracked: {}\x1b[0m", text);
+        }
+        if unmarked > 0 {
+            println!("   \x1b[33m⚠️  {} planned dish(es) never marked eaten or skipped, left out\x1b[0m", unmarked);
+        }
+
+        let mut content = content;
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(PANTRY_UPDATED);
+        content.push('\n');
+        fs::write(&path, content).context(format!("Failed to write {:?}", path))?;
+        applied += 1;
+    }
+
+    if applied == 0 {
+        println!("✅ No finished journals waiting for the pantry");
+    } else {
+        println!("\n✅ Applied {} journal(s) to {}", applied, pantry_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shop-automation-journal-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const JOURNAL: &str = "\
+# Meal Journal - July 09, 2025
+
+## Breakfast
+- [x] @./Breakfast/Shakshuka.cook{2}
+
+## Lunch
+- 
+
+## Dinner
+- [-] @rice{200%g}
+- [ ] @kefir{200%ml}
+
+## Notes
+- Planned: 3 Day Plan VI, Day 1
+";
+
+    #[test]
+    fn reads_marks_and_unplanned_entries() {
+        let entry = |line| match parse_entry(line) {
+            Some(Entry::Planned { mark, text }) => Some((Some(mark), text)),
+            Some(Entry::Unplanned(text)) => Some((None, text)),
+            None => None,
+        };
+        assert_eq!(entry("- [x] @eggs{2}"), Some((Some(Mark::Eaten), "@eggs{2}")));
+        assert_eq!(entry("  - [X] @eggs{2}"), Some((Some(Mark::Eaten), "@eggs{2}")));
+        assert_eq!(entry("- [-] @rice{200%g}"), Some((Some(Mark::Skipped), "@rice{200%g}")));
+        assert_eq!(entry("- [ ] @kefir{200%ml}"), Some((Some(Mark::Unmarked), "@kefir{200%ml}")));
+        assert_eq!(entry("- @banana{1}"), Some((None, "@banana{1}")));
+        assert_eq!(entry("- "), None);
+        assert_eq!(entry("## Dinner"), None);
+    }
+
+    #[test]
+    fn finds_a_meals_lines_up_to_the_next_heading() {
+        let journal = lines(JOURNAL);
+        assert_eq!(meal_range(&journal, "breakfast"), Some((3, 5)));
+        assert_eq!(meal_range(&journal, "Dinner"), Some((9, 12)));
+        assert_eq!(meal_range(&journal, "Snacks"), None);
+    }
+
+    #[test]
+    fn logs_into_the_placeholder_or_after_the_last_entry() {
+        let dir = temp_dir("ate");
+        let date = NaiveDate::from_ymd_opt(2025, 7, 9).unwrap();
+        fs::write(journal_path(&dir, date), JOURNAL).unwrap();
+
+        ate(&dir, date, "Lunch", "@banana{1}").unwrap();
+        ate(&dir, date, "Dinner", "@yoghurt{1}").unwrap();
+        let journal = fs::read_to_string(journal_path(&dir, date)).unwrap();
+        assert!(journal.contains("## Lunch\n- @banana{1}\n\n## Dinner"));
+        assert!(journal.contains("- [ ] @kefir{200%ml}\n- @yoghurt{1}\n\n## Notes"));
+        assert!(ate(&dir, date, "Supper", "@tea{1}").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn applies_a_day_to_the_pantry_only_once() {
+        let root = temp_dir("pantry");
+        let dir = root.join("journal");
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(root.join("config")).unwrap();
+        let db_path = root.join("config").join("db").to_string_lossy().to_string();
+        let pantry_path = pantry::pantry_path(&db_path);
+        let journal = journal_path(&dir, NaiveDate::from_ymd_opt(2025, 7, 9).unwrap());
+        fs::write(&journal, JOURNAL).unwrap();
+
+        update_pantry(&db_path, &dir, std::slice::from_ref(&journal)).unwrap();
+        let pantry = fs::read_to_string(&pantry_path).unwrap();
+        // Only the skipped rice; the unmarked kefir is left out
+        assert!(pantry.contains("rice"));
+        assert!(!pantry.contains("kefir"));
+
+        update_pantry(&db_path, &dir, std::slice::from_ref(&journal)).unwrap();
+        assert_eq!(fs::read_to_string(&pantry_path).unwrap(), pantry);
+        assert_eq!(fs::read_to_string(&journal).unwrap().matches(PANTRY_UPDATED).count(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
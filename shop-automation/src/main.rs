@@ -2,9 +2,27 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::io::{self, BufRead, Read};
 
+mod calendar;
+mod cart;
+mod cost;
+mod db;
+mod delivery;
+mod journal;
+mod menu;
 mod scraper;
 mod models;
+mod names;
+mod nutrition;
+mod pantry;
+mod planner;
+mod profile;
+mod price;
+mod recipes;
+mod settings;
 mod shopper;
+mod substitutions;
+mod vault;
+mod weaning;
 
 #[derive(Parser)]
 #[command(name = "shop-automation")]
@@ -18,13 +36,32 @@ struct Cli {
 enum Commands {
     /// Scrape product information from SuperValu
     Scrape {
-        /// Base path for the database (default: ../config/db)
-        #[arg(long, default_value = "../config/db")]
-        db_path: String,
+        /// Base path for the database (default: ../config/db, or the profile's db_path)
+        #[arg(long)]
+        db_path: Option<String>,
+        
+        /// Named profile from `profiles:` in config/shop-automation.yml (own session, store and delivery preferences)
+        #[arg(long)]
+        profile: Option<String>,
         
         /// Run in visible mode (show browser window)
         #[arg(long, short = 'v')]
         visible: bool,
+        
+        /// Also fetch nutrition_facts.yml from the primary option's product page, for entries without one
+        #[arg(long)]
+        nutrition: bool,
+    },
+    
+    /// Inspect and curate product options in the database.
+    /// Commands that change a shopping.yml rewrite it, dropping any comments.
+    Db {
+        /// Base path for the database (default: ../config/db)
+        #[arg(long, default_value = "../config/db", global = true)]
+        db_path: String,
+        
+        #[command(subcommand)]
+        command: DbCommands,
     },
     
     /// Login to SuperValu and save session cookies
@@ -36,12 +73,153 @@ enum Commands {
         /// Manual login - browser stays open for you to login yourself
         #[arg(long, short = 'm')]
         manual: bool,
+        
+        /// Keep the session in an encrypted vault instead of a plain cookie file
+        #[arg(long)]
+        encrypt: bool,
+        
+        /// Also keep the account email and password in the vault (implies --encrypt)
+        #[arg(long)]
+        save_password: bool,
+        
+        /// Use a persistent Chrome profile managed by shop-automation instead of a cookie file
+        #[arg(long)]
+        chrome_profile: bool,
+        
+        /// Named profile from `profiles:` in config/shop-automation.yml (own session, store and delivery preferences)
+        #[arg(long)]
+        profile: Option<String>,
+        
+        /// Base path for the database, where the profiles are configured (default: ../config/db)
+        #[arg(long)]
+        db_path: Option<String>,
+    },
+    
+    /// Inspect the saved SuperValu session
+    Session {
+        /// Named profile from `profiles:` in config/shop-automation.yml
+        #[arg(long, global = true)]
+        profile: Option<String>,
+        
+        /// Check the persistent Chrome profile instead of the cookie file
+        #[arg(long, global = true)]
+        chrome_profile: bool,
+        
+        /// Base path for the database, where the profiles are configured (default: ../config/db)
+        #[arg(long, global = true)]
+        db_path: Option<String>,
+        
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+    
+    /// The SuperValu basket of the saved session
+    Cart {
+        /// Named profile from `profiles:` in config/shop-automation.yml
+        #[arg(long, global = true)]
+        profile: Option<String>,
+        
+        /// Use the persistent Chrome profile instead of the cookie file
+        #[arg(long, global = true)]
+        chrome_profile: bool,
+        
+        /// Base path for the database, where the profiles are configured (default: ../config/db)
+        #[arg(long, global = true)]
+        db_path: Option<String>,
+        
+        /// Run in visible mode (show browser window)
+        #[arg(long, short = 'v', global = true)]
+        visible: bool,
+        
+        #[command(subcommand)]
+        command: CartCommands,
+    },
+    
+    /// Estimate what a .cook recipe or .menu plan costs from db prices
+    Cost {
+        /// Recipe or menu file
+        file: String,
+        
+        /// Option to price each item by (default: option_policy from config/shop-automation.yml)
+        #[arg(long, value_enum)]
+        policy: Option<settings::OptionPolicy>,
+        
+        /// Base path for the database
+        #[arg(long, default_value = "../config/db")]
+        db_path: String,
+    },
+    
+    /// Nutrition of a .cook recipe or .menu plan from db nutrition facts
+    Nutrition {
+        /// Recipe or menu file
+        file: String,
+        
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: nutrition::Format,
+        
+        /// Base path for the database
+        #[arg(long, default_value = "../config/db")]
+        db_path: String,
+    },
+    
+    /// Meal plans (.menu files)
+    Plan {
+        /// Base path for the database
+        #[arg(long, default_value = "../config/db", global = true)]
+        db_path: String,
+        
+        #[command(subcommand)]
+        command: PlanCommands,
+    },
+    
+    /// Baby weaning log: one new food every few days
+    Weaning {
+        /// Base path for the database (the log is config/weaning.yml next to it)
+        #[arg(long, default_value = "../config/db", global = true)]
+        db_path: String,
+        
+        #[command(subcommand)]
+        command: WeaningCommands,
+    },
+    
+    /// Daily meal journals: what was planned and what was actually eaten
+    Journal {
+        /// Base path for the database (pantry.conf sits next to it)
+        #[arg(long, default_value = "../config/db", global = true)]
+        db_path: String,
+        
+        /// Journal directory (default: journal/ at the recipes root)
+        #[arg(long, global = true)]
+        dir: Option<String>,
+        
+        #[command(subcommand)]
+        command: JournalCommands,
     },
     
     /// Shop for items from a YAML shopping list
     Shop {
         /// Path to shopping list YAML file (use '-' for stdin)
-        shopping_list: String,
+        shopping_list: Option<String>,
+        
+        /// Shop for an item by name, trying all of its db options (repeatable)
+        #[arg(long = "item")]
+        items: Vec<String>,
+        
+        /// Order to try db options in (default: option_policy from config/shop-automation.yml)
+        #[arg(long, value_enum)]
+        policy: Option<settings::OptionPolicy>,
+        
+        /// Stop adding once the cart would cost more than this many euros (default: budget from config/shop-automation.yml)
+        #[arg(long)]
+        budget: Option<f64>,
+        
+        /// Base path for the database, used to fill in missing links (default: ../config/db, or the profile's db_path)
+        #[arg(long)]
+        db_path: Option<String>,
+        
+        /// Named profile from `profiles:` in config/shop-automation.yml (own session, store and delivery preferences)
+        #[arg(long)]
+        profile: Option<String>,
         
         /// Run in visible mode (show browser window)
         #[arg(long, short = 'v')]
@@ -50,6 +228,239 @@ enum Commands {
         /// Force fresh login even if cookies exist
         #[arg(long)]
         force_login: bool,
+        
+        /// After adding, make the cart match the list: add what's missing, fix quantities, remove extras
+        #[arg(long)]
+        fix_cart: bool,
+        
+        /// Use a persistent Chrome profile managed by shop-automation instead of a cookie file
+        #[arg(long)]
+        chrome_profile: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PlanCommands {
+    /// Write a new Plans/*.menu from recipes picked by the plan rules
+    Generate {
+        /// Rules file (default: config/plan-rules.yml next to the db)
+        #[arg(long)]
+        rules: Option<String>,
+        
+        /// Menu file to write (default: the next "N Day Plan" in Plans/)
+        #[arg(long, short = 'o')]
+        output: Option<String>,
+        
+        /// Seed for the recipe picks, to reproduce a plan
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    
+    /// Export a .menu as calendar events: meals, prep reminders and the delivery slot
+    Export {
+        /// .menu plan
+        menu: String,
+        
+        /// Date of the menu's first day (Day 0 with a delivery day), YYYY-MM-DD
+        #[arg(long)]
+        start: chrono::NaiveDate,
+        
+        /// Calendar file format
+        #[arg(long, value_enum, default_value = "ics")]
+        format: calendar::Format,
+        
+        /// File to write (default: the menu's name with .ics)
+        #[arg(long, short = 'o')]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WeaningCommands {
+    /// Show introduced foods, reactions and what's next
+    Status,
+    
+    /// Log a new food
+    Introduce {
+        food: String,
+        
+        /// Day it was first given, YYYY-MM-DD (default: today)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+    },
+    
+    /// Record how a food went: "none", or what happened
+    React {
+        food: String,
+        reaction: String,
+        
+        #[arg(long)]
+        notes: Option<String>,
+    },
+    
+    /// Add the next new food and a repeat food to a .menu's Weaning section or a shopping list YAML
+    Add {
+        /// .menu plan or shopping list YAML (created if missing)
+        target: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionCommands {
+    /// Whether the session still logs in, as whom, when its cookies expire and the store
+    Status {
+        /// Run in visible mode (show browser window)
+        #[arg(long, short = 'v')]
+        visible: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CartCommands {
+    /// Line items with product id, quantity and prices, the subtotal and fees
+    Show {
+        #[arg(long, value_enum, default_value = "table")]
+        format: cart::Format,
+        
+        /// File for the JSON/YAML; required with those formats, as progress goes to stdout
+        #[arg(long, short = 'o', required_if_eq_any([("format", "json"), ("format", "yaml")]))]
+        output: Option<String>,
+    },
+    
+    /// Take lines out of the cart
+    Remove {
+        /// Product id, product URL, or the name or whole words of it (repeatable)
+        #[arg(required = true)]
+        items: Vec<String>,
+        
+        /// Remove every line a name matches, not only when it matches one
+        #[arg(long)]
+        all: bool,
+    },
+    
+    /// Empty the cart, e.g. before a `shop` run
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum JournalCommands {
+    /// Write a journal per day of a .menu, prefilled with the planned dishes
+    Create {
+        /// .menu plan
+        menu: String,
+        
+        /// Date of the menu's first day, YYYY-MM-DD
+        #[arg(long)]
+        start: chrono::NaiveDate,
+        
+        /// Overwrite journals that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Mark a meal's planned dishes as eaten (or skipped)
+    Mark {
+        /// Breakfast, Lunch, Dinner or Snacks
+        meal: String,
+        
+        /// Only dishes containing this text (default: the whole meal)
+        #[arg(long)]
+        dish: Option<String>,
+        
+        /// Day, YYYY-MM-DD (default: today)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+        
+        /// Mark them skipped instead, so `update-pantry` puts them back as leftovers
+        #[arg(long)]
+        not_eaten: bool,
+    },
+    
+    /// Log something eaten that wasn't planned, e.g. "@banana{1}"
+    Ate {
+        /// Breakfast, Lunch, Dinner or Snacks
+        meal: String,
+        
+        text: String,
+        
+        /// Day, YYYY-MM-DD (default: today)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+    },
+    
+    /// Put leftovers back in pantry.conf and take out unplanned food
+    UpdatePantry {
+        /// Journals to apply (default: every past day not applied yet)
+        files: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// List all items with their primary option
+    List,
+    
+    /// Show all stored options for an item
+    Show {
+        /// Item name (resolved like recipe ingredients, e.g. "onions")
+        item: String,
+    },
+    
+    /// Make an option the primary one (opt_1), shifting the others down
+    Promote {
+        item: String,
+        
+        /// Option to promote as `db show` prints it, e.g. opt_3 (or just 3)
+        option: String,
+    },
+    
+    /// Remove an option and renumber the rest
+    RemoveOption {
+        item: String,
+        
+        /// Option to remove as `db show` prints it, e.g. opt_2 (or just 2)
+        option: String,
+    },
+    
+    /// Fetch a product page and add it as a new option
+    Add {
+        item: String,
+        
+        /// SuperValu product URL
+        url: String,
+        
+        /// Run in visible mode (show browser window)
+        #[arg(long, short = 'v')]
+        visible: bool,
+    },
+    
+    /// Visit stored URLs and mark delisted products as dead
+    Verify {
+        /// Only verify these items (default: all)
+        items: Vec<String>,
+        
+        /// How many product pages to check at once
+        #[arg(long, default_value_t = 3)]
+        concurrency: usize,
+        
+        /// Search for replacements when an item has no live options left
+        #[arg(long)]
+        rescrape: bool,
+        
+        /// Named profile whose store `--rescrape` searches
+        #[arg(long)]
+        profile: Option<String>,
+        
+        /// Run in visible mode (show browser window)
+        #[arg(long, short = 'v')]
+        visible: bool,
+    },
+    
+    /// Report broken entries, bad URLs and gaps between recipes and the db
+    Check {
+        /// Directory with .cook and .menu files (default: two levels above the db)
+        #[arg(long)]
+        recipes_dir: Option<String>,
     },
 }
 
@@ -58,12 +469,13 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Scrape { db_path, visible } => {
+        Commands::Scrape { db_path, profile, visible, nutrition } => {
+            let (profile, db_path, _) = profile::resolve(profile.as_deref(), db_path)?;
             let stdin = io::stdin();
             let products: Vec<String> = stdin
                 .lock()
                 .lines()
-                .filter_map(|line| line.ok())
+                .map_while(|line| line.ok())
                 .filter(|line| !line.trim().is_empty())
                 .collect();
 
@@ -77,50 +489,169 @@ async fn main() -> Result<()> {
                 println!("Running in visible mode - browser windows will be shown");
             }
             
+            let mut nutrition = nutrition.then(|| scraper::NutritionFetcher::new(visible));
             for product in products {
                 println!("Scraping: {}", product);
-                match scraper::scrape_product(&product, &db_path, visible).await {
+                match scraper::scrape_product(&product, &db_path, &profile.store, visible).await {
                     Ok(_) => println!("✓ Successfully scraped {}", product),
                     Err(e) => eprintln!("\x1b[31m✗ Failed to scrape {}: {}\x1b[0m", product, e),
                 }
+                if let Some(fetcher) = &mut nutrition
+                    && let Err(e) = fetcher.fetch_missing(&product, &db_path)
+                {
+                    println!("\x1b[33m⚠ Couldn't fetch nutrition for {}: {:#}\x1b[0m", product, e);
+                }
             }
         }
-        Commands::Login { visible, manual } => {
+        Commands::Db { db_path, command } => match command {
+            DbCommands::List => db::list(&db_path)?,
+            DbCommands::Show { item } => db::show(&db_path, &item)?,
+            DbCommands::Promote { item, option } => db::promote(&db_path, &item, &option)?,
+            DbCommands::RemoveOption { item, option } => db::remove_option(&db_path, &item, &option)?,
+            DbCommands::Add { item, url, visible } => db::add(&db_path, &item, &url, visible).await?,
+            DbCommands::Verify { items, concurrency, rescrape, profile, visible } => {
+                let (profile, _, _) = profile::resolve(profile.as_deref(), Some(db_path.clone()))?;
+                db::verify(&db_path, &profile, &items, concurrency, rescrape, visible).await?;
+            }
+            DbCommands::Check { recipes_dir } => {
+                let recipes_dir = recipes_dir
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(|| recipes::recipes_root(&db_path));
+                db::check(&db_path, &recipes_dir)?;
+            }
+        },
+        Commands::Cost { file, policy, db_path } => {
+            let settings = settings::Settings::load(&db_path)?;
+            let policy = policy.unwrap_or(settings.option_policy);
+            cost::cost(&db_path, std::path::Path::new(&file), policy)?;
+        }
+        Commands::Nutrition { file, format, db_path } => {
+            nutrition::nutrition(&db_path, std::path::Path::new(&file), format)?;
+        }
+        Commands::Plan { db_path, command } => match command {
+            PlanCommands::Generate { rules, output, seed } => {
+                let rules = rules
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(|| planner::rules_path(&db_path));
+                planner::generate(&db_path, &rules, output.map(std::path::PathBuf::from), seed)?;
+            }
+            PlanCommands::Export { menu, start, format, output } => {
+                let menu = std::path::PathBuf::from(menu);
+                let output = output
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(|| menu.with_extension(format.extension()));
+                match format {
+                    calendar::Format::Ics => calendar::export_ics(&menu, start, &output)?,
+                }
+            }
+        },
+        Commands::Weaning { db_path, command } => match command {
+            WeaningCommands::Status => weaning::status(&db_path)?,
+            WeaningCommands::Introduce { food, date } => weaning::introduce(&db_path, &food, date)?,
+            WeaningCommands::React { food, reaction, notes } => weaning::react(&db_path, &food, &reaction, notes)?,
+            WeaningCommands::Add { target } => weaning::add_to(&db_path, std::path::Path::new(&target))?,
+        },
+        Commands::Journal { db_path, dir, command } => {
+            let dir = dir
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| journal::journal_dir(&db_path));
+            let today = chrono::Local::now().date_naive();
+            match command {
+                JournalCommands::Create { menu, start, force } => {
+                    journal::create(std::path::Path::new(&menu), start, &dir, force)?;
+                }
+                JournalCommands::Mark { meal, dish, date, not_eaten } => {
+                    journal::mark(&dir, date.unwrap_or(today), &meal, dish.as_deref(), !not_eaten)?;
+                }
+                JournalCommands::Ate { meal, text, date } => journal::ate(&dir, date.unwrap_or(today), &meal, &text)?,
+                JournalCommands::UpdatePantry { files } => {
+                    let files: Vec<std::path::PathBuf> = files.iter().map(std::path::PathBuf::from).collect();
+                    journal::update_pantry(&db_path, &dir, &files)?;
+                }
+            }
+        }
+        Commands::Cart { profile, chrome_profile, db_path, visible, command } => {
+            dotenv::dotenv().ok();
+            
+            let (mut profile, _, _) = profile::resolve(profile.as_deref(), db_path)?;
+            profile.chrome_profile |= chrome_profile;
+            match command {
+                CartCommands::Show { format, output } => {
+                    cart::show(&profile, visible, format, output.as_deref().map(std::path::Path::new))?;
+                }
+                CartCommands::Remove { items, all } => cart::remove(&profile, visible, &items, all)?,
+                CartCommands::Clear => cart::clear(&profile, visible)?,
+            }
+        }
+        Commands::Session { profile, chrome_profile, db_path, command } => {
+            let (mut profile, _, _) = profile::resolve(profile.as_deref(), db_path)?;
+            profile.chrome_profile |= chrome_profile;
+            match command {
+                SessionCommands::Status { visible } => {
+                    if !shopper::session_status(&profile, visible)? {
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Commands::Login { visible, manual, encrypt, save_password, chrome_profile, profile, db_path } => {
             // Load environment variables
             dotenv::dotenv().ok();
             
+            let (mut profile, _, _) = profile::resolve(profile.as_deref(), db_path)?;
+            profile.chrome_profile |= chrome_profile;
+            if profile.chrome_profile && encrypt {
+                println!("⚠️  --encrypt only applies to cookie files; the Chrome profile is stored as Chrome keeps it");
+            }
             if manual {
-                println!("🔐 Opening SuperValu for manual login...");
-                shopper::manual_login_and_save_cookies()?;
+                println!("🔐 Opening SuperValu for manual login ({})...", profile.describe());
+                shopper::manual_login_and_save_cookies(&profile, encrypt, save_password)?;
             } else {
-                println!("🔐 Logging in to SuperValu...");
-                shopper::login_and_save_cookies(visible).await?;
+                println!("🔐 Logging in to SuperValu ({})...", profile.describe());
+                shopper::login_and_save_cookies(&profile, visible, encrypt, save_password).await?;
             }
         }
-        Commands::Shop { shopping_list: shopping_list_path, visible, force_login } => {
+        Commands::Shop { shopping_list: shopping_list_path, items, policy, budget, db_path, profile, visible, force_login, fix_cart, chrome_profile } => {
             // Load environment variables
             dotenv::dotenv().ok();
             
-            // Read shopping list
-            let input = if shopping_list_path == "-" {
-                // Read from stdin
-                let mut input = String::new();
-                io::stdin().read_to_string(&mut input)?;
-                input
-            } else {
-                // Read from file
-                std::fs::read_to_string(&shopping_list_path)
-                    .context(format!("Failed to read shopping list from {}", shopping_list_path))?
+            let mut shopping_list = match shopping_list_path {
+                Some(shopping_list_path) => {
+                    // Read shopping list
+                    let input = if shopping_list_path == "-" {
+                        // Read from stdin
+                        let mut input = String::new();
+                        io::stdin().read_to_string(&mut input)?;
+                        input
+                    } else {
+                        // Read from file
+                        std::fs::read_to_string(&shopping_list_path)
+                            .context(format!("Failed to read shopping list from {}", shopping_list_path))?
+                    };
+                    
+                    // Parse YAML
+                    serde_yaml::from_str(&input)
+                        .context("Failed to parse shopping list YAML")?
+                }
+                None if !items.is_empty() => models::ShoppingList { items: Vec::new() },
+                None => anyhow::bail!("Provide a shopping list file ('-' for stdin) or at least one --item"),
             };
+            shopping_list.items.extend(items.iter().map(|name| models::ShoppingItem::named(name)));
             
-            // Parse YAML
-            let shopping_list: models::ShoppingList = serde_yaml::from_str(&input)
-                .context("Failed to parse shopping list YAML")?;
+            let (mut profile, db_path, settings) = profile::resolve(profile.as_deref(), db_path)?;
+            profile.chrome_profile |= chrome_profile;
+            let options = shopper::ShopOptions {
+                policy: policy.unwrap_or(settings.option_policy),
+                budget: budget.or(settings.budget),
+                visible,
+                force_login,
+                fix_cart,
+            };
             
             println!("🛒 Starting shopping automation with {} items", shopping_list.items.len());
             
             // Run shopping automation
-            shopper::shop_items(shopping_list, visible, force_login).await?;
+            shopper::shop_items(shopping_list, &db_path, &profile, options).await?;
         }
     }
 
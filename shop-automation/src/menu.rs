@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use crate::recipes::{self, Ingredient};
+
+// `.menu` plans: front matter, then sections like `==Day 1==` or `== Snacks ==`
+// holding `Breakfast:` / `Lunch:` / `Dinner:` headers, `- dish` lines and
+// `-- notes` such as "-- delivery around 4pm".
+
+#[derive(Debug)]
+pub struct Dish {
+    /// The line as written, without the leading "- "
+    pub text: String,
+    pub ingredients: Vec<Ingredient>,
+}
+
+impl Dish {
+    /// Readable text without the cooklang markup:
+    /// "@./Dinners/Carbonara.cook{2}" -> "Carbonara",
+    /// "@green salad{} with @olive oil{1%tbsp}" -> "green salad with olive oil"
+    pub fn title(&self) -> String {
+        let mut title = String::new();
+        let mut rest = self.text.as_str();
+        for ingredient in &self.ingredients {
+            let Some(at) = rest.find(&format!("@{}", ingredient.name)) else {
+                break;
+            };
+            title.push_str(&rest[..at]);
+            rest = &rest[at + 1 + ingredient.name.len()..];
+
+            let name = if ingredient.is_recipe_reference() {
+                let stem = ingredient.name.rsplit('/').next().unwrap_or(&ingredient.name);
+                stem.strip_suffix(".cook").unwrap_or(stem)
+            } else {
+                ingredient.name.as_str()
+            };
+            title.push_str(name);
+
+            for (open, close) in [('{', '}'), ('(', ')')] {
+                if let Some(inner) = rest.strip_prefix(open)
+                    && let Some(end) = inner.find(close)
+                {
+                    rest = &inner[end + 1..];
+                }
+            }
+        }
+        title.push_str(rest);
+        title.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[derive(Debug)]
+pub struct Meal {
+    /// "Breakfast", "Lunch", ...; empty for dishes listed straight under the section
+    pub name: String,
+    pub dishes: Vec<Dish>,
+}
+
+#[derive(Debug)]
+pub struct Section {
+    /// "Day 0", "Snacks", "Baby", ...
+    pub title: String,
+    /// Set for `==Day N==` sections
+    pub day: Option<u32>,
+    pub meals: Vec<Meal>,
+    /// `-- notes`, in order, without the dashes
+    pub notes: Vec<String>,
+}
+
+impl Section {
+    pub fn dishes(&self) -> impl Iterator<Item = &Dish> {
+        self.meals.iter().flat_map(|meal| meal.dishes.iter())
+    }
+
+    pub fn meal(&self, name: &str) -> Option<&Meal> {
+        self.meals.iter().find(|meal| meal.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug)]
+pub struct Menu {
+    /// People the plan is cooked for, from the front matter
+    pub servings: Option<f64>,
+    pub sections: Vec<Section>,
+}
+
+impl Menu {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read menu {:?}", path))?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let (front_matter, body) = recipes::split_front_matter(text);
+        let servings = recipes::parse_front_matter(front_matter)
+            .get("servings")
+            .and_then(|servings| recipes::parse_amount(servings));
+
+        let mut sections: Vec<Section> = Vec::new();
+        for line in body.lines() {
+            let line = line.trim();
+
+            if line.starts_with("==") {
+                let title = line.trim_matches('=').trim().to_string();
+                let day = title
+                    .strip_prefix("Day")
+                    .and_then(|n| n.trim().parse().ok());
+                sections.push(Section {
+                    title,
+                    day,
+                    meals: Vec::new(),
+                    notes: Vec::new(),
+                });
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+            if sections.is_empty() {
+                sections.push(Section {
+                    title: String::new(),
+                    day: None,
+                    meals: Vec::new(),
+                    notes: Vec::new(),
+                });
+            }
+            let section = sections.last_mut().expect("section pushed above");
+
+            if let Some(note) = line.strip_prefix("--") {
+                let note = note.trim();
+                if !note.is_empty() {
+                    section.notes.push(note.to_string());
+                }
+            } else if let Some(name) = line.strip_suffix(':')
+                && !name.contains('@')
+            {
+                section.meals.push(Meal {
+                    name: name.trim().to_string(),
+                    dishes: Vec::new(),
+                });
+            } else {
+                let text = line.strip_prefix('-').unwrap_or(line);
+                let text = text.split(" --").next().unwrap_or(text).trim();
+                if section.meals.is_empty() {
+                    section.meals.push(Meal {
+                        name: String::new(),
+                        dishes: Vec::new(),
+                    });
+                }
+                let meal = section.meals.last_mut().expect("meal pushed above");
+                meal.dishes.push(Dish {
+                    text: text.to_string(),
+                    ingredients: recipes::parse_line_ingredients(text),
+                });
+            }
+        }
+
+        Self {
+            servings,
+            sections,
+        }
+    }
+
+    pub fn days(&self) -> impl Iterator<Item = &Section> {
+        self.sections.iter().filter(|section| section.day.is_some())
+    }
+}
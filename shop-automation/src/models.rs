@@ -1,7 +1,10 @@
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProductOption {
     pub name: String,
     pub url: String,
@@ -9,36 +12,248 @@ pub struct ProductOption {
     pub price_per_unit: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub quantity: Option<String>,
+    /// Set by `db verify` when the product page is gone, e.g. "not found"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead: Option<String>,
+    // Hand-added keys survive a load/save round trip
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShoppingData {
+    #[serde(serialize_with = "serialize_options")]
     pub supervalu: HashMap<String, ProductOption>,
+    // Other stores are kept as-is
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl ShoppingData {
     pub fn new() -> Self {
         Self {
             supervalu: HashMap::new(),
+            other: BTreeMap::new(),
         }
     }
 
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read {:?}", path))?;
+        serde_yaml::from_str(&content)
+            .context(format!("Failed to parse {:?}", path))
+    }
+
+    /// Write via a temp file so an interrupted save never leaves a truncated shopping.yml
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let yaml_content = serde_yaml::to_string(self)
+            .context("Failed to serialize to YAML")?;
+        let tmp_path = path.with_extension("yml.tmp");
+        fs::write(&tmp_path, yaml_content)
+            .context(format!("Failed to write file: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .context(format!("Failed to write file: {:?}", path))?;
+        Ok(())
+    }
+
     pub fn add_option(&mut self, index: usize, option: ProductOption) {
         let key = format!("opt_{}", index);
         self.supervalu.insert(key, option);
     }
+
+    /// Options ordered by their `opt_N` index, primary first
+    pub fn sorted_options(&self) -> Vec<(&String, &ProductOption)> {
+        let mut options: Vec<_> = self.supervalu.iter().collect();
+        options.sort_by_key(|(key, _)| option_index(key));
+        options
+    }
+
+    /// Replace all options, numbering them `opt_1..opt_N` in the given order
+    pub fn set_options(&mut self, options: Vec<ProductOption>) {
+        self.supervalu.clear();
+        for (i, option) in options.into_iter().enumerate() {
+            self.add_option(i + 1, option);
+        }
+    }
+
+    /// Take the options out in `opt_N` order
+    pub fn take_options(&mut self) -> Vec<ProductOption> {
+        let mut options: Vec<_> = self.supervalu.drain().collect();
+        options.sort_by_key(|(key, _)| option_index(key));
+        options.into_iter().map(|(_, option)| option).collect()
+    }
+
+    /// True when the file is only the placeholder `scrape` leaves behind
+    pub fn is_empty_template(&self) -> bool {
+        self.supervalu.values().all(|opt| opt.url.is_empty() && opt.name.is_empty())
+    }
 }
 
-// Shopping list item from YAML input
+fn serialize_options<S>(options: &HashMap<String, ProductOption>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    let mut sorted: Vec<_> = options.iter().collect();
+    sorted.sort_by_key(|(key, _)| option_index(key));
+
+    let mut map = serializer.serialize_map(Some(sorted.len()))?;
+    for (key, option) in sorted {
+        map.serialize_entry(key, option)?;
+    }
+    map.end()
+}
+
+/// Numeric index of an `opt_N` key; unknown keys sort last
+pub fn option_index(key: &str) -> usize {
+    key.strip_prefix("opt_")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(usize::MAX)
+}
+
+/// How badly an item is needed; nice-to-haves are dropped first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    #[default]
+    Must,
+    Nice,
+}
+
+// Shopping list item from YAML input. Everything after `backup_link` is
+// optional so lists from the jinja template keep working.
 #[derive(Debug, Deserialize)]
 pub struct ShoppingItem {
+    /// Also used to look up every stored option in the db
     pub name: String,
     pub amount: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_if_null")]
     pub link: String,
     pub backup_link: Option<String>,
+    /// Nice-to-haves are dropped rather than blowing the `--budget`;
+    /// `optional: true` is shorthand for `priority: nice`
+    #[serde(flatten, deserialize_with = "priority_or_optional")]
+    pub priority: Priority,
+    /// Shown while shopping, e.g. "ripe ones for guacamole"
+    pub note: Option<String>,
+    /// Most we'll pay for one pack, in euros
+    pub max_price: Option<f64>,
+    /// Number of packs to put in the cart
+    pub quantity: Option<u32>,
+    /// Where to buy it; items for other stores are left out of the SuperValu cart
+    pub store: Option<String>,
+    /// e.g. `baby`; `never tag:baby` in substitutions.conf keeps them exact
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ShoppingItem {
+    /// Item with no links; `shop` finds its options in the db by name
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            amount: None,
+            link: String::new(),
+            backup_link: None,
+            priority: Priority::Must,
+            note: None,
+            max_price: None,
+            quantity: None,
+            store: None,
+            tags: Vec::new(),
+        }
+    }
+    
+    pub fn is_optional(&self) -> bool {
+        self.priority == Priority::Nice
+    }
+    
+    pub fn packs(&self) -> u32 {
+        self.quantity.unwrap_or(1).max(1)
+    }
+    
+    pub fn is_for_supervalu(&self) -> bool {
+        self.store.as_deref().is_none_or(|store| store.eq_ignore_ascii_case("supervalu"))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ShoppingList {
     pub items: Vec<ShoppingItem>,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+struct PriorityFields {
+    priority: Option<Priority>,
+    optional: Option<bool>,
+}
+
+fn priority_or_optional<'de, D>(deserializer: D) -> std::result::Result<Priority, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let fields = PriorityFields::deserialize(deserializer)?;
+    match (fields.priority, fields.optional) {
+        (Some(Priority::Must), Some(true)) => {
+            Err(serde::de::Error::custom("`optional: true` means `priority: nice`, but the item also says `priority: must`"))
+        }
+        (Some(priority), _) => Ok(priority),
+        (None, Some(true)) => Ok(Priority::Nice),
+        (None, _) => Ok(Priority::Must),
+    }
+}
+
+// The jinja template emits `link: ` when the db has no entry, which YAML reads as null
+fn empty_string_if_null<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(yaml: &str) -> Vec<ShoppingItem> {
+        serde_yaml::from_str::<ShoppingList>(yaml).unwrap().items
+    }
+
+    #[test]
+    fn reads_the_jinja_templates_output_as_written() {
+        // As Reports/shopping-list.yaml.jinja renders it, with one item missing from the db
+        let yaml = "items:
+    - name: butter
+      amount: 250 g
+      link: https://shop.supervalu.ie/sm/delivery/rsid/404/product/butter-id-1
+      backup_link: https://shop.supervalu.ie/sm/delivery/rsid/404/product/butter-id-2 # alternative product if not available -
+    - name: saffron
+      amount: 1 pinch
+      link: 
+      backup_link:  # alternative product if not available -
+";
+
+        let items = items(yaml);
+        assert_eq!(items.len(), 2);
+        let butter = &items[0];
+        assert_eq!(butter.name, "butter");
+        assert_eq!(butter.amount.as_deref(), Some("250 g"));
+        assert_eq!(butter.link, "https://shop.supervalu.ie/sm/delivery/rsid/404/product/butter-id-1");
+        assert_eq!(butter.backup_link.as_deref(), Some("https://shop.supervalu.ie/sm/delivery/rsid/404/product/butter-id-2"));
+        assert_eq!((butter.priority, butter.packs(), butter.is_for_supervalu()), (Priority::Must, 1, true));
+        assert!(butter.note.is_none() && butter.max_price.is_none() && butter.tags.is_empty());
+
+        let saffron = &items[1];
+        assert_eq!((saffron.link.as_str(), saffron.backup_link.as_deref()), ("", None));
+    }
+
+    #[test]
+    fn optional_is_shorthand_for_priority_nice() {
+        let items = items("items:\n  - name: a\n    optional: true\n  - name: b\n    priority: nice\n  - name: c\n    optional: false\n  - name: d\n    optional: true\n    priority: nice\n");
+        let priorities: Vec<Priority> = items.iter().map(|item| item.priority).collect();
+        assert_eq!(priorities, [Priority::Nice, Priority::Nice, Priority::Must, Priority::Nice]);
+
+        assert!(serde_yaml::from_str::<ShoppingList>("items:\n  - name: a\n    optional: true\n    priority: must\n").is_err());
+    }
+}
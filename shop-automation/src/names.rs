@@ -0,0 +1,267 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Canonical names for `config/db` entries.
+//
+// Recipes refer to the same product in many ways (`@onions`, `@onion`,
+// `@panchetta`), while the db is keyed by directory name. Every lookup goes
+// through `NameResolver` so that scrape and shop agree on which directory
+// a product lives in.
+
+/// How a name was matched to a db directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Exact,
+    Alias,
+    Plural,
+    Fuzzy,
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub key: String,
+    pub kind: MatchKind,
+}
+
+pub struct NameResolver {
+    db_path: PathBuf,
+    entries: Vec<String>,
+    aliases: HashMap<String, String>,
+}
+
+/// Directory key for a product name, as used by `scrape` since the beginning
+pub fn clean_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .replace(" ", "_")
+        .replace("/", "_")
+        .replace("\\", "_")
+}
+
+/// Aliases live next to the db: `config/db` -> `config/aliases.yml`
+pub fn aliases_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("aliases.yml")
+}
+
+/// Fold the last word of a key to its singular form (`cherry_tomatoes` -> `cherry_tomato`,
+/// `bay_leaves` -> `bay_leaf`)
+pub fn singular(key: &str) -> String {
+    let (head, word) = match key.rfind('_') {
+        Some(pos) => (&key[..=pos], &key[pos + 1..]),
+        None => ("", key),
+    };
+
+    let folded = if word.len() <= 3 || word.ends_with("ss") || word.ends_with("us") {
+        word.to_string()
+    } else if let Some(stem) = word.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if word.ends_with("lves") || word.ends_with("eaves") || word.ends_with("oaves") {
+        // bay_leaves, loaves, halves; but not olives or chives
+        format!("{}f", &word[..word.len() - 3])
+    } else if let Some(stem) = word.strip_suffix("oes") {
+        format!("{}o", stem)
+    } else if word.ends_with("ches") || word.ends_with("shes") || word.ends_with("xes") {
+        word[..word.len() - 2].to_string()
+    } else if let Some(stem) = word.strip_suffix('s') {
+        stem.to_string()
+    } else {
+        word.to_string()
+    };
+
+    format!("{}{}", head, folded)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+impl NameResolver {
+    pub fn load(db_path: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        if let Ok(dir) = fs::read_dir(db_path) {
+            for entry in dir.flatten() {
+                if entry.path().is_dir() {
+                    entries.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        entries.sort();
+
+        let mut aliases = HashMap::new();
+        let alias_file = aliases_path(db_path);
+        if alias_file.exists() {
+            let content = fs::read_to_string(&alias_file)
+                .context(format!("Failed to read aliases from {:?}", alias_file))?;
+            // canonical: [variant, variant, ...]
+            let groups: HashMap<String, Vec<String>> = serde_yaml::from_str(&content)
+                .context(format!("Failed to parse aliases in {:?}", alias_file))?;
+            for (canonical, variants) in groups {
+                for variant in variants {
+                    aliases.insert(clean_name(&variant), clean_name(&canonical));
+                }
+            }
+        }
+
+        Ok(Self {
+            db_path: PathBuf::from(db_path),
+            entries,
+            aliases,
+        })
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn has_entry(&self, key: &str) -> bool {
+        self.entries.binary_search_by(|e| e.as_str().cmp(key)).is_ok()
+    }
+
+    /// Find the existing db directory for a product name, if any
+    pub fn resolve(&self, name: &str) -> Option<Resolved> {
+        let key = clean_name(name);
+        if key.is_empty() {
+            return None;
+        }
+
+        // Aliases win: they are how we curate duplicates by hand
+        if let Some(target) = self.aliases.get(&key) {
+            return Some(Resolved { key: target.clone(), kind: MatchKind::Alias });
+        }
+
+        // Prefer the singular directory over the plural one when both exist
+        let single = singular(&key);
+        if single != key && self.has_entry(&single) {
+            return Some(Resolved { key: single, kind: MatchKind::Plural });
+        }
+
+        if self.has_entry(&key) {
+            return Some(Resolved { key, kind: MatchKind::Exact });
+        }
+
+        if let Some(entry) = self.entries.iter().find(|e| singular(e) == single) {
+            return Some(Resolved { key: entry.clone(), kind: MatchKind::Plural });
+        }
+
+        // Fuzzy matching catches typos like `panchetta`; allow one edit per 5 chars
+        let max_distance = single.chars().count() / 5;
+        if max_distance == 0 {
+            return None;
+        }
+
+        let mut best: Option<(usize, &String)> = None;
+        let mut tied = false;
+        for entry in &self.entries {
+            let distance = levenshtein(&single, &singular(entry));
+            if distance > max_distance {
+                continue;
+            }
+            match best {
+                Some((d, _)) if distance > d => {}
+                Some((d, _)) if distance == d => tied = true,
+                _ => {
+                    best = Some((distance, entry));
+                    tied = false;
+                }
+            }
+        }
+
+        match best {
+            Some((_, entry)) if !tied => Some(Resolved { key: entry.clone(), kind: MatchKind::Fuzzy }),
+            _ => None,
+        }
+    }
+
+    /// Directory key to use for a product: the resolved entry or a fresh clean name
+    pub fn canonical_key(&self, name: &str) -> String {
+        self.resolve(name)
+            .map(|r| r.key)
+            .unwrap_or_else(|| clean_name(name))
+    }
+
+    pub fn shopping_yaml_path(&self, key: &str) -> PathBuf {
+        self.db_path.join(key).join("shopping.yml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(entries: &[&str], aliases: &[(&str, &str)]) -> NameResolver {
+        let mut entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        entries.sort();
+        NameResolver {
+            db_path: PathBuf::from("db"),
+            entries,
+            aliases: aliases.iter().map(|(variant, canonical)| (variant.to_string(), canonical.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn singular_folds_the_last_word() {
+        assert_eq!(singular("cherry_tomatoes"), "cherry_tomato");
+        assert_eq!(singular("berries"), "berry");
+        assert_eq!(singular("bay_leaves"), "bay_leaf");
+        assert_eq!(singular("olives"), "olive");
+        assert_eq!(singular("peaches"), "peach");
+        assert_eq!(singular("hummus"), "hummus");
+        assert_eq!(singular("egg"), "egg");
+    }
+
+    #[test]
+    fn resolves_aliases_first() {
+        let names = resolver(&["pancetta", "bacon"], &[("lardons", "pancetta")]);
+        let resolved = names.resolve("Lardons").unwrap();
+        assert_eq!(resolved.key, "pancetta");
+        assert_eq!(resolved.kind, MatchKind::Alias);
+    }
+
+    #[test]
+    fn resolves_plurals_to_the_singular_entry() {
+        let names = resolver(&["onion", "onions", "bay_leaf"], &[]);
+        let resolved = names.resolve("onions").unwrap();
+        assert_eq!((resolved.key.as_str(), resolved.kind), ("onion", MatchKind::Plural));
+        let resolved = names.resolve("bay leaves").unwrap();
+        assert_eq!((resolved.key.as_str(), resolved.kind), ("bay_leaf", MatchKind::Plural));
+        let resolved = names.resolve("onion").unwrap();
+        assert_eq!(resolved.kind, MatchKind::Exact);
+    }
+
+    #[test]
+    fn resolves_typos_fuzzily() {
+        let names = resolver(&["pancetta", "parmesan"], &[]);
+        let resolved = names.resolve("panchetta").unwrap();
+        assert_eq!((resolved.key.as_str(), resolved.kind), ("pancetta", MatchKind::Fuzzy));
+        // Too short to allow an edit
+        assert!(names.resolve("pea").is_none());
+    }
+
+    #[test]
+    fn leaves_ambiguous_names_unresolved() {
+        let names = resolver(&["bream", "cream"], &[]);
+        assert!(names.resolve("dream").is_none());
+        assert_eq!(names.canonical_key("dream"), "dream");
+    }
+}
@@ -0,0 +1,416 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use crate::menu::Menu;
+use crate::names::{NameResolver, singular};
+use crate::price::{self, PriceUnit};
+use crate::recipes::{self, Ingredient, Recipe};
+
+// Nutrition of a recipe or menu from `config/db/<item>/nutrition_facts.yml`
+// (values per `serving_size`, 100 g when missing) and `physics.yml`, which
+// turns "1 medium" or "2 tbsp" into grams:
+//
+//   density: 0.75 g/cm3
+//   units:
+//     - 1 medium = 75g = 1/2cup
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Nutrients {
+    pub calories: f64,
+    pub protein: f64,
+    pub carbohydrate: f64,
+    pub fat: f64,
+    pub fiber: f64,
+    pub sugars: f64,
+}
+
+impl Nutrients {
+    fn add(&mut self, other: &Self) {
+        self.calories += other.calories;
+        self.protein += other.protein;
+        self.carbohydrate += other.carbohydrate;
+        self.fat += other.fat;
+        self.fiber += other.fiber;
+        self.sugars += other.sugars;
+    }
+
+    fn scaled(&self, factor: f64) -> Self {
+        Self {
+            calories: self.calories * factor,
+            protein: self.protein * factor,
+            carbohydrate: self.carbohydrate * factor,
+            fat: self.fat * factor,
+            fiber: self.fiber * factor,
+            sugars: self.sugars * factor,
+        }
+    }
+}
+
+/// What we know about one db item
+struct Facts {
+    /// Nutrients in one gram
+    per_gram: Nutrients,
+    /// Grams per ml
+    density: f64,
+    /// "medium" -> 75 g
+    unit_grams: HashMap<String, f64>,
+}
+
+#[derive(Serialize)]
+struct MealReport {
+    name: String,
+    total: Nutrients,
+}
+
+#[derive(Serialize)]
+struct SectionReport {
+    title: String,
+    /// Breakfast, lunch, ... of a menu day; only in JSON
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    meals: Vec<MealReport>,
+    total: Nutrients,
+    /// Per serving of a recipe, per person for a menu day
+    per_serving: Option<Nutrients>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    file: String,
+    servings: Option<f64>,
+    sections: Vec<SectionReport>,
+    /// Days only, so snacks and batch prep don't skew it
+    per_day: Option<Nutrients>,
+    not_counted: Vec<String>,
+}
+
+/// "0.3g", "65kcal", "7.5mg", "3.8mcg" -> grams (or kcal)
+fn parse_value(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let number: f64 = text[..split].parse().ok()?;
+
+    match text[split..].trim().to_lowercase().as_str() {
+        "" | "g" | "kcal" => Some(number),
+        "kg" => Some(number * 1000.0),
+        "mg" => Some(number / 1000.0),
+        "mcg" | "µg" => Some(number / 1_000_000.0),
+        _ => None,
+    }
+}
+
+fn load_facts(item_dir: &Path) -> Result<Option<Facts>> {
+    let path = item_dir.join("nutrition_facts.yml");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .context(format!("Failed to read {:?}", path))?;
+    let mut facts = parse_facts(&content).context(format!("Failed to parse {:?}", path))?;
+
+    let physics_path = item_dir.join("physics.yml");
+    if physics_path.exists() {
+        let content = fs::read_to_string(&physics_path)
+            .context(format!("Failed to read {:?}", physics_path))?;
+        parse_physics(&content, &mut facts).context(format!("Failed to parse {:?}", physics_path))?;
+    }
+
+    Ok(Some(facts))
+}
+
+/// The contents of a `nutrition_facts.yml`
+fn parse_facts(content: &str) -> Result<Facts> {
+    let yaml: serde_yaml::Value = serde_yaml::from_str(content)?;
+
+    let text = |value: &serde_yaml::Value| match value {
+        serde_yaml::Value::String(s) => parse_value(s),
+        serde_yaml::Value::Number(n) => n.as_f64(),
+        _ => None,
+    };
+    let macro_value = |name: &str| yaml["macronutrients"].get(name).and_then(text).unwrap_or_default();
+
+    let serving = yaml.get("serving_size").and_then(text).unwrap_or(100.0);
+    if serving <= 0.0 {
+        bail!("serving_size has to be more than 0");
+    }
+    let per_serving = Nutrients {
+        calories: yaml.get("calories").and_then(text).unwrap_or_default(),
+        protein: macro_value("protein"),
+        carbohydrate: macro_value("carbohydrate"),
+        fat: macro_value("fat"),
+        fiber: macro_value("fiber"),
+        sugars: macro_value("sugars"),
+    };
+
+    Ok(Facts {
+        per_gram: per_serving.scaled(1.0 / serving),
+        density: 1.0,
+        unit_grams: HashMap::new(),
+    })
+}
+
+/// Density and units from the contents of a `physics.yml`
+fn parse_physics(content: &str, facts: &mut Facts) -> Result<()> {
+    let physics: serde_yaml::Value = serde_yaml::from_str(content)?;
+
+    if let Some(density) = physics.get("density").and_then(|d| d.as_str()) {
+        facts.density = density.split_whitespace().next().and_then(|d| d.parse().ok()).unwrap_or(1.0);
+    }
+    // "1 medium = 75g = 1/2cup"
+    for unit in physics["units"].as_sequence().into_iter().flatten().filter_map(|u| u.as_str()) {
+        let parts: Vec<&str> = unit.split('=').map(str::trim).collect();
+        if let [first, grams, ..] = parts[..]
+            && let Some((count, name)) = first.split_once(' ')
+            && let (Some(count), Some(grams)) = (recipes::parse_amount(count), parse_value(grams))
+            && count > 0.0
+        {
+            facts.unit_grams.insert(singular(name.trim()), grams / count);
+        }
+    }
+    Ok(())
+}
+
+/// Grams of an ingredient, via the item's own units first ("1 slice = 30g")
+fn grams(ingredient: &Ingredient, facts: &Facts) -> Option<f64> {
+    let amount = ingredient.amount?;
+    let unit = ingredient.unit.as_deref().unwrap_or_default().trim().to_lowercase();
+
+    if let Some(grams) = facts.unit_grams.get(&singular(&unit)) {
+        return Some(amount * grams);
+    }
+
+    match price::convert(amount, &unit)? {
+        (kg, PriceUnit::Kg) => Some(kg * 1000.0),
+        (litres, PriceUnit::Litre) => Some(litres * 1000.0 * facts.density),
+        // A bare count: "medium" is the usual size
+        (count, PriceUnit::Each) => ["medium", "piece", "each"]
+            .iter()
+            .find_map(|size| facts.unit_grams.get(*size))
+            .map(|grams| count * grams),
+    }
+}
+
+struct Calculator<'a> {
+    resolver: &'a NameResolver,
+    facts: HashMap<String, Option<Facts>>,
+    not_counted: BTreeSet<String>,
+}
+
+impl Calculator<'_> {
+    fn total(&mut self, ingredients: &[Ingredient]) -> Result<Nutrients> {
+        let mut total = Nutrients::default();
+
+        for ingredient in ingredients {
+            let key = self.resolver.canonical_key(&ingredient.name);
+            if !self.facts.contains_key(&key) {
+                let facts = load_facts(&self.resolver.db_path().join(&key))?;
+                self.facts.insert(key.clone(), facts);
+            }
+
+            let name = ingredient.name.to_lowercase();
+            match &self.facts[&key] {
+                None => {
+                    self.not_counted.insert(format!("{} (no nutrition facts; `scrape --nutrition` fetches them)", name));
+                }
+                Some(facts) => match grams(ingredient, facts) {
+                    Some(grams) => total.add(&facts.per_gram.scaled(grams)),
+                    None => {
+                        self.not_counted.insert(format!("{} (amount can't be weighed)", name));
+                    }
+                },
+            }
+        }
+
+        Ok(total)
+    }
+}
+
+pub fn nutrition(db_path: &str, file: &Path, format: Format) -> Result<()> {
+    let root = recipes::recipes_root(db_path);
+    let resolver = NameResolver::load(db_path)?;
+    let mut calculator = Calculator {
+        resolver: &resolver,
+        facts: HashMap::new(),
+        not_counted: BTreeSet::new(),
+    };
+
+    let is_menu = file.extension().is_some_and(|ext| ext == "menu");
+    let mut sections = Vec::new();
+    let servings;
+    let mut per_day = None;
+
+    if is_menu {
+        let menu = Menu::load(file)?;
+        servings = menu.servings;
+
+        let mut days = 0;
+        let mut days_total = Nutrients::default();
+        for section in &menu.sections {
+            let mut meals = Vec::new();
+            let mut total = Nutrients::default();
+            for meal in &section.meals {
+                let mut ingredients = Vec::new();
+                for dish in &meal.dishes {
+                    ingredients.extend(recipes::expand(&root, file, dish.ingredients.clone())?);
+                }
+                let meal_total = calculator.total(&ingredients)?;
+                total.add(&meal_total);
+                if !meal.name.is_empty() {
+                    meals.push(MealReport {
+                        name: meal.name.clone(),
+                        total: meal_total,
+                    });
+                }
+            }
+            if section.dishes().all(|dish| dish.ingredients.is_empty()) {
+                continue;
+            }
+
+            if section.day.is_some() {
+                days += 1;
+                days_total.add(&total);
+            }
+            // Snacks, baby food and batch prep aren't shared out per person
+            sections.push(SectionReport {
+                title: section.title.clone(),
+                meals,
+                total,
+                per_serving: servings.filter(|_| section.day.is_some()).map(|s| total.scaled(1.0 / s)),
+            });
+        }
+        if days > 0 {
+            per_day = Some(days_total.scaled(1.0 / days as f64));
+        }
+    } else {
+        servings = Recipe::load(file)?.servings();
+        let total = calculator.total(&recipes::expand_ingredients(&root, file)?)?;
+        sections.push(SectionReport {
+            title: file.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            meals: Vec::new(),
+            total,
+            per_serving: servings.map(|s| total.scaled(1.0 / s)),
+        });
+    }
+
+    let report = Report {
+        file: file.display().to_string(),
+        servings,
+        sections,
+        per_day,
+        not_counted: calculator.not_counted.into_iter().collect(),
+    };
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        Format::Markdown => print_markdown(&report, is_menu),
+    }
+
+    Ok(())
+}
+
+fn print_markdown(report: &Report, is_menu: bool) {
+    let row = |label: &str, n: &Nutrients| {
+        println!(
+            "| {} | {:.0} | {:.1} g | {:.1} g | {:.1} g | {:.1} g | {:.1} g |",
+            label, n.calories, n.protein, n.carbohydrate, n.fat, n.fiber, n.sugars
+        );
+    };
+    let per = if is_menu { "per person" } else { "per serving" };
+
+    println!("# Nutrition - {}\n", Path::new(&report.file).file_stem().unwrap_or_default().to_string_lossy());
+    if let Some(servings) = report.servings {
+        println!("Servings: {}\n", servings);
+    }
+    println!("| | kcal | Protein | Carbs | Fat | Fiber | Sugars |");
+    println!("|---|---|---|---|---|---|---|");
+    for section in &report.sections {
+        row(&section.title, &section.total);
+        if let Some(per_serving) = &section.per_serving {
+            row(&format!("{} ({})", section.title, per), per_serving);
+        }
+    }
+    if let Some(per_day) = &report.per_day {
+        row("**Average day**", per_day);
+        if let Some(servings) = report.servings {
+            row(&format!("**Average day ({})**", per), &per_day.scaled(1.0 / servings));
+        }
+    }
+
+    if !report.not_counted.is_empty() {
+        println!("\n## Not counted\n");
+        for item in &report.not_counted {
+            println!("- {}", item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ingredient(amount: f64, unit: &str) -> Ingredient {
+        Ingredient { name: "apple".to_string(), amount: Some(amount), unit: Some(unit.to_string()) }
+    }
+
+    #[test]
+    fn parses_values_in_grams() {
+        assert_eq!(parse_value("0.3g"), Some(0.3));
+        assert_eq!(parse_value("65kcal"), Some(65.0));
+        assert_eq!(parse_value("7.5mg"), Some(0.0075));
+        assert_eq!(parse_value("2 kg"), Some(2000.0));
+        assert_eq!(parse_value("12"), Some(12.0));
+        assert_eq!(parse_value("a pinch"), None);
+        assert_eq!(parse_value("3 cups"), None);
+    }
+
+    #[test]
+    fn scales_facts_to_a_gram() {
+        let facts = parse_facts("serving_size: 125g\ncalories: 65kcal\nmacronutrients:\n  protein: 0.3g\n").unwrap();
+        assert!((facts.per_gram.calories - 0.52).abs() < 1e-9);
+        assert!((facts.per_gram.protein - 0.0024).abs() < 1e-9);
+
+        let per_100g = parse_facts("calories: 200\n").unwrap();
+        assert!((per_100g.per_gram.calories - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn refuses_a_zero_serving_size() {
+        assert!(parse_facts("serving_size: 0\ncalories: 65kcal\n").is_err());
+        assert!(parse_facts("serving_size: 0g\ncalories: 65kcal\n").is_err());
+    }
+
+    #[test]
+    fn reads_units_and_density_from_physics() {
+        let mut facts = parse_facts("calories: 52kcal\n").unwrap();
+        parse_physics("density: 0.75 g/cm3\nunits:\n  - 1 medium = 75g = 1/2cup\n  - 2 slices = 60g\n  - a handful\n", &mut facts).unwrap();
+
+        assert_eq!(facts.density, 0.75);
+        assert_eq!(facts.unit_grams.get("medium"), Some(&75.0));
+        assert_eq!(facts.unit_grams.get("slice"), Some(&30.0));
+        assert_eq!(facts.unit_grams.len(), 2);
+    }
+
+    #[test]
+    fn weighs_ingredients() {
+        let mut facts = parse_facts("calories: 52kcal\n").unwrap();
+        parse_physics("density: 0.5 g/cm3\nunits:\n  - 1 medium = 75g\n  - 1 slice = 30g\n", &mut facts).unwrap();
+
+        assert_eq!(grams(&ingredient(250.0, "g"), &facts), Some(250.0));
+        assert_eq!(grams(&ingredient(0.5, "kg"), &facts), Some(500.0));
+        assert_eq!(grams(&ingredient(2.0, "slices"), &facts), Some(60.0));
+        // Volumes go through the density, bare counts through "medium"
+        assert_eq!(grams(&ingredient(100.0, "ml"), &facts), Some(50.0));
+        assert_eq!(grams(&ingredient(2.0, ""), &facts), Some(150.0));
+        assert_eq!(grams(&ingredient(1.0, "pinch"), &facts), None);
+        assert_eq!(grams(&Ingredient { name: "apple".to_string(), amount: None, unit: None }, &facts), None);
+    }
+}
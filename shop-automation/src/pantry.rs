@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::names::{clean_name, singular};
+use crate::price;
+use crate::recipes::{Ingredient, parse_amount};
+
+// Reader for CookCLI's `config/pantry.conf`. Items listed there are excluded
+// from shopping lists, so they don't need a db entry either.
+
+#[derive(Debug, Clone)]
+pub struct PantryItem {
+    pub name: String,
+}
+
+/// The pantry sits next to the db: `config/db` -> `config/pantry.conf`
+pub fn pantry_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("pantry.conf")
+}
+
+pub fn load(path: &Path) -> Result<Vec<PantryItem>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)
+        .context(format!("Failed to read pantry from {:?}", path))?;
+
+    let mut items = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+        // `garlic = "3"` or `"red onion" = "3"`
+        if let Some((key, _)) = line.split_once('=') {
+            let name = key.trim().trim_matches('"').to_string();
+            if !name.is_empty() {
+                items.push(PantryItem { name });
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+/// `"garlic" = "3"` -> ("garlic", "3"); comments and section headers give `None`
+fn parse_entry(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+        return None;
+    }
+    let (key, value) = line.split_once('=')?;
+    Some((key.trim().trim_matches('"').to_string(), value.trim().trim_matches('"').to_string()))
+}
+
+/// How many of the pantry's unit one ingredient amount is, e.g. 250 g against "2%kg" -> 0.25
+fn in_pantry_unit(ingredient: &Ingredient, pantry_unit: &str) -> Option<f64> {
+    let amount = ingredient.amount?;
+    let unit = ingredient.unit.as_deref().unwrap_or_default();
+    if singular(&unit.to_lowercase()) == singular(&pantry_unit.to_lowercase()) {
+        return Some(amount);
+    }
+
+    let (base, unit) = price::convert(amount, unit)?;
+    let (per_pantry_unit, pantry) = price::convert(1.0, pantry_unit)?;
+    (unit == pantry).then_some(base / per_pantry_unit)
+}
+
+/// `garlic = "3"`, quoting keys with spaces like the rest of the file
+fn format_entry(name: &str, value: &str) -> String {
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        format!("{} = \"{}\"", name, value)
+    } else {
+        format!("\"{}\" = \"{}\"", name, value)
+    }
+}
+
+fn format_amount(amount: f64, unit: &str) -> String {
+    let amount = (amount * 100.0).round() / 100.0;
+    if unit.is_empty() {
+        format!("{}", amount)
+    } else {
+        format!("{}%{}", amount, unit)
+    }
+}
+
+/// Add `entry` at the end of the `[section]` table, creating it at the end of
+/// the file if it's missing. Appending at EOF would put it in whichever table
+/// comes last.
+fn insert_into_section(lines: &mut Vec<String>, section: &str, entry: String) {
+    let header = format!("[{}]", section);
+    let start = match lines.iter().position(|line| line.trim() == header) {
+        Some(index) => index,
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.len() - 1
+        }
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim().starts_with('['))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    // After the section's last line, before the blank lines leading to the next one
+    let after = (start..end).rev().find(|&i| !lines[i].trim().is_empty()).unwrap_or(start) + 1;
+    lines.insert(after, entry);
+}
+
+/// Update stock after meals went differently from the plan: `leftovers` were
+/// bought but not eaten, `eaten` came out of the pantry unplanned. Entries are
+/// changed where the units agree; measured leftovers the pantry doesn't list
+/// yet go in a `[journal]` section. Returns a line per change.
+pub fn adjust(path: &Path, leftovers: &[Ingredient], eaten: &[Ingredient]) -> Result<Vec<String>> {
+    let content = if path.exists() {
+        fs::read_to_string(path).context(format!("Failed to read pantry from {:?}", path))?
+    } else {
+        String::new()
+    };
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut changes = Vec::new();
+
+    let changes_in = leftovers.iter().map(|i| (i, 1.0)).chain(eaten.iter().map(|i| (i, -1.0)));
+    for (ingredient, sign) in changes_in {
+        let key = clean_name(&ingredient.name);
+        let existing = lines
+            .iter()
+            .position(|line| parse_entry(line).is_some_and(|(name, _)| clean_name(&name) == key));
+
+        match existing {
+            Some(index) => {
+                let (name, value) = parse_entry(&lines[index]).expect("matched above");
+                let (amount, unit) = match value.split_once('%') {
+                    Some((amount, unit)) => (parse_amount(amount), unit.to_string()),
+                    None => (parse_amount(&value), String::new()),
+                };
+                // "unlim", "some" and mismatched units stay as they are
+                let (Some(amount), Some(delta)) = (amount, in_pantry_unit(ingredient, &unit)) else {
+                    continue;
+                };
+                let updated = format_amount((amount + sign * delta).max(0.0), &unit);
+                changes.push(format!("{}: {} -> {}", name, value, updated));
+                lines[index] = format_entry(&name, &updated);
+            }
+            None if sign > 0.0 => {
+                let Some(amount) = ingredient.amount else {
+                    continue;
+                };
+                let unit = ingredient.unit.clone().unwrap_or_default();
+                let name = ingredient.name.to_lowercase();
+                let value = format_amount(amount, &unit);
+                changes.push(format!("{}: new, {}", name, value));
+                insert_into_section(&mut lines, "journal", format_entry(&name, &value));
+            }
+            None => {}
+        }
+    }
+
+    if !changes.is_empty() {
+        fs::write(path, lines.join("\n") + "\n").context(format!("Failed to write pantry {:?}", path))?;
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn inserts_into_an_existing_section_before_the_next_one() {
+        let mut pantry = lines("[journal]\nrice = \"1%kg\"\n\n[garden]\nbasil = \"unlim\"");
+        insert_into_section(&mut pantry, "journal", "oats = \"0.5%kg\"".to_string());
+        assert_eq!(pantry, lines("[journal]\nrice = \"1%kg\"\noats = \"0.5%kg\"\n\n[garden]\nbasil = \"unlim\""));
+    }
+
+    #[test]
+    fn creates_the_section_at_the_end() {
+        let mut pantry = lines("[garden]\nbasil = \"unlim\"");
+        insert_into_section(&mut pantry, "journal", "oats = \"0.5%kg\"".to_string());
+        assert_eq!(pantry, lines("[garden]\nbasil = \"unlim\"\n\n[journal]\noats = \"0.5%kg\""));
+    }
+}
@@ -0,0 +1,509 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::recipes::{self, Recipe};
+
+// `plan generate`: fills a new `Plans/*.menu` with recipes picked by the rules
+// in `config/plan-rules.yml`, laid out like the plans we write by hand:
+// a delivery Day 0 with dinner, then full days, then snacks and batch prep.
+
+#[derive(Debug, Deserialize)]
+pub struct MealRule {
+    /// "Breakfast", "Lunch", "Dinner"
+    pub name: String,
+    /// Folders under the recipes root to pick from
+    pub folders: Vec<String>,
+    /// Recipes must have all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// Each matching tag makes a recipe more likely to be picked
+    #[serde(default)]
+    pub prefer_tags: Vec<String>,
+    /// Preferred `course`, e.g. "Main Course"
+    pub course: Option<String>,
+    /// Longest `time required`, e.g. "45 minutes"; recipes without a time are allowed
+    pub max_time: Option<String>,
+    /// Same dish every day
+    #[serde(default)]
+    pub repeat: bool,
+    /// Days one batch covers; 2 means `{1/2}` on each of two days
+    #[serde(default = "one")]
+    pub leftovers: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlanRules {
+    #[serde(default = "default_days")]
+    pub days: u32,
+    #[serde(default = "default_servings")]
+    pub servings: u32,
+    /// Start with a Day 0 that only has dinner, after the delivery
+    #[serde(default)]
+    pub delivery_day: bool,
+    pub meals: Vec<MealRule>,
+    /// Items for the `== Snacks ==` section
+    #[serde(default)]
+    pub snacks: Vec<String>,
+    pub batch_prep: Option<MealRule>,
+}
+
+fn default_days() -> u32 {
+    3
+}
+
+fn default_servings() -> u32 {
+    2
+}
+
+pub fn rules_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("plan-rules.yml")
+}
+
+impl PlanRules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read plan rules from {:?}", path))?;
+        serde_yaml::from_str(&content)
+            .context(format!("Failed to parse plan rules in {:?}", path))
+    }
+}
+
+/// Tiny xorshift generator; plans only need to vary, not be unpredictable
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+struct Candidate {
+    /// Path under the recipes root without `.cook`, as menus reference it
+    reference: String,
+    weight: u64,
+}
+
+/// "4 hours 20 minutes", "5h15m", "90 minutes", "1 hour", "15m"
+pub fn parse_minutes(text: &str) -> Option<u32> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut found = false;
+    let text = text.to_lowercase();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        if c == '-' {
+            // "7-8 hours": keep the upper bound
+            number.clear();
+            continue;
+        }
+        if let Ok(value) = number.parse::<f64>() {
+            match c {
+                'h' => total += value * 60.0,
+                'm' => total += value,
+                _ => {}
+            }
+            found = found || matches!(c, 'h' | 'm');
+        }
+        number.clear();
+        // Skip the rest of the word: "hours", "minutes"
+        while chars.peek().is_some_and(|c| c.is_alphabetic()) {
+            chars.next();
+        }
+    }
+
+    found.then_some(total.round() as u32)
+}
+
+fn recipe_minutes(recipe: &Recipe) -> Option<u32> {
+    for key in ["time required", "time", "total time"] {
+        if let Some(minutes) = recipe.metadata.get(key).and_then(|t| parse_minutes(t)) {
+            return Some(minutes);
+        }
+    }
+
+    let part = |keys: &[&str]| keys.iter().find_map(|key| recipe.metadata.get(*key).and_then(|t| parse_minutes(t)));
+    match (part(&["prep time", "prep_time", "time prep"]), part(&["cook time", "cook_time"])) {
+        (None, None) => None,
+        (prep, cook) => Some(prep.unwrap_or_default() + cook.unwrap_or_default()),
+    }
+}
+
+fn has_tag(tags: &[String], wanted: &str) -> bool {
+    let wanted = wanted.to_lowercase();
+    tags.iter().any(|tag| tag.contains(&wanted))
+}
+
+fn candidates(root: &Path, rule: &MealRule) -> Result<Vec<Candidate>> {
+    let max_time = match &rule.max_time {
+        Some(text) => Some(parse_minutes(text).context(format!("Can't read max_time '{}' for {}", text, rule.name))?),
+        None => None,
+    };
+
+    let mut candidates = Vec::new();
+    for folder in &rule.folders {
+        let dir = root.join(folder);
+        if !dir.is_dir() {
+            bail!("Folder '{}' for {} not found under {:?}", folder, rule.name, root);
+        }
+
+        for file in recipes::find_recipe_files(&dir) {
+            if file.extension().is_none_or(|ext| ext != "cook") {
+                continue;
+            }
+            let recipe = Recipe::load(&file)?;
+            let tags: Vec<String> = recipe
+                .metadata
+                .get("tags")
+                .map(|tags| tags.split(',').map(|t| t.trim().to_lowercase()).collect())
+                .unwrap_or_default();
+
+            if !rule.tags.iter().all(|tag| has_tag(&tags, tag))
+                || rule.exclude_tags.iter().any(|tag| has_tag(&tags, tag))
+            {
+                continue;
+            }
+            if let (Some(max_time), Some(minutes)) = (max_time, recipe_minutes(&recipe))
+                && minutes > max_time
+            {
+                continue;
+            }
+
+            let mut weight = 1 + 2 * rule.prefer_tags.iter().filter(|tag| has_tag(&tags, tag)).count() as u64;
+            if let (Some(course), Some(recipe_course)) = (&rule.course, recipe.metadata.get("course"))
+                && recipe_course.to_lowercase().contains(&course.to_lowercase())
+            {
+                weight += 2;
+            }
+
+            let relative = file.strip_prefix(root).unwrap_or(&file).with_extension("");
+            candidates.push(Candidate {
+                reference: format!("./{}", relative.display()),
+                weight,
+            });
+        }
+    }
+
+    // Directory order varies between filesystems; a `--seed` should give the same plan anywhere
+    candidates.sort_by(|a, b| a.reference.cmp(&b.reference));
+    Ok(candidates)
+}
+
+/// Weighted pick, avoiding recipes already in the plan while there are others
+fn pick(candidates: &[Candidate], used: &mut BTreeSet<String>, rng: &mut Rng) -> Option<String> {
+    let fresh: Vec<&Candidate> = candidates.iter().filter(|c| !used.contains(&c.reference)).collect();
+    let pool: Vec<&Candidate> = if fresh.is_empty() { candidates.iter().collect() } else { fresh };
+
+    let total: u64 = pool.iter().map(|c| c.weight).sum();
+    let mut ticket = rng.below(total);
+    for candidate in pool {
+        if ticket < candidate.weight {
+            used.insert(candidate.reference.clone());
+            return Some(candidate.reference.clone());
+        }
+        ticket -= candidate.weight;
+    }
+    None
+}
+
+/// Dishes for one meal across `days`, honouring `repeat` and `leftovers`
+fn plan_meal(rule: &MealRule, candidates: &[Candidate], days: usize, servings: u32, used: &mut BTreeSet<String>, rng: &mut Rng) -> Vec<Option<String>> {
+    let mut dishes = Vec::with_capacity(days);
+    let batch = if rule.repeat { days } else { rule.leftovers.max(1) as usize };
+
+    while dishes.len() < days {
+        let Some(reference) = pick(candidates, used, rng) else {
+            dishes.resize(days, None);
+            break;
+        };
+        // A batch cut short by the end of the plan is shared over the days it has
+        let covered = batch.min(days - dishes.len());
+        let amount = if covered > 1 && !rule.repeat {
+            format!("1/{}", covered)
+        } else {
+            format!("{}%servings", servings)
+        };
+        for _ in 0..covered {
+            dishes.push(Some(format!("- @{}{{{}}}", reference, amount)));
+        }
+    }
+
+    dishes
+}
+
+fn to_roman(mut n: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            roman.push_str(numeral);
+            n -= value;
+        }
+    }
+    roman
+}
+
+/// "3 Day Plan VII.menu" after "3 Day Plan VI.menu"
+fn next_plan_path(plans_dir: &Path, days: u32) -> PathBuf {
+    let prefix = format!("{} Day Plan", days);
+    (1..)
+        .map(|n| plans_dir.join(format!("{} {}.menu", prefix, to_roman(n))))
+        .find(|path| !path.exists())
+        .expect("some plan number is free")
+}
+
+pub fn generate(db_path: &str, rules_file: &Path, output: Option<PathBuf>, seed: Option<u64>) -> Result<()> {
+    let root = recipes::recipes_root(db_path);
+    let rules = PlanRules::load(rules_file)?;
+    if rules.days == 0 {
+        bail!("Plan rules need at least one day");
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(1)
+    });
+    let mut rng = Rng::new(seed);
+    let mut used = BTreeSet::new();
+
+    // Every meal is planned for `days` days. With a delivery day, dinners run
+    // Day 0 to the day before last, since the last dinner comes from the next order.
+    let days: Vec<u32> = if rules.delivery_day { (0..=rules.days).collect() } else { (1..=rules.days).collect() };
+    let mut planned: Vec<(&MealRule, Vec<Option<String>>)> = Vec::new();
+    for rule in &rules.meals {
+        let candidates = candidates(&root, rule)?;
+        if candidates.is_empty() {
+            println!("⚠️  No recipes match the rules for {}", rule.name);
+        }
+        planned.push((rule, plan_meal(rule, &candidates, rules.days as usize, rules.servings, &mut used, &mut rng)));
+    }
+
+    let mut menu = String::new();
+    writeln!(menu, "---\nservings: {}\n---", rules.servings)?;
+
+    for day in &days {
+        writeln!(menu, "\n==Day {}==", day)?;
+        if *day == 0 {
+            writeln!(menu, "\n-- delivery around 4pm")?;
+        }
+
+        for (rule, dishes) in &planned {
+            let is_dinner = rule.name.eq_ignore_ascii_case("dinner");
+            // Index into this meal's dishes: dinners shift by one with a delivery day
+            let index = match (rules.delivery_day, is_dinner) {
+                (true, true) if *day == rules.days => None,
+                (true, true) => Some(*day as usize),
+                (true, false) if *day == 0 => None,
+                (true, false) => Some(*day as usize - 1),
+                (false, _) => Some(*day as usize - 1),
+            };
+            if let Some(Some(dish)) = index.and_then(|i| dishes.get(i)) {
+                writeln!(menu, "\n{}:\n{}", rule.name, dish)?;
+            }
+        }
+
+        if rules.delivery_day && *day == rules.days {
+            writeln!(menu, "\n-- dinner from a new order")?;
+        }
+    }
+
+    writeln!(menu, "\n== Snacks ==\n")?;
+    for snack in &rules.snacks {
+        writeln!(menu, "- @{}{{}}", snack)?;
+    }
+
+    let batch_title = rules.batch_prep.as_ref().map_or("Batch Prep", |rule| rule.name.as_str());
+    writeln!(menu, "\n== {} ==\n", batch_title)?;
+    match &rules.batch_prep {
+        Some(rule) => {
+            let candidates = candidates(&root, rule)?;
+            match pick(&candidates, &mut used, &mut rng) {
+                Some(reference) => writeln!(menu, "- @{}{{}}", reference)?,
+                None => writeln!(menu, "-- nothing")?,
+            }
+        }
+        None => writeln!(menu, "-- nothing")?,
+    }
+
+    writeln!(menu, "\n== Baby ==")?;
+
+    let output = output.unwrap_or_else(|| next_plan_path(&root.join("Plans"), rules.days));
+    if output.exists() {
+        bail!("{:?} already exists", output);
+    }
+    fs::write(&output, menu).context(format!("Failed to write {:?}", output))?;
+
+    println!("📅 Wrote {} (seed {})", output.display(), seed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_recipe_times() {
+        assert_eq!(parse_minutes("4 hours 20 minutes"), Some(260));
+        assert_eq!(parse_minutes("5h15m"), Some(315));
+        assert_eq!(parse_minutes("90 minutes"), Some(90));
+        assert_eq!(parse_minutes("1.5 hours"), Some(90));
+        // The upper bound of a range
+        assert_eq!(parse_minutes("7-8 hours"), Some(480));
+        assert_eq!(parse_minutes("overnight"), None);
+    }
+
+    #[test]
+    fn numbers_plans_in_roman_numerals() {
+        assert_eq!(to_roman(4), "IV");
+        assert_eq!(to_roman(9), "IX");
+        assert_eq!(to_roman(14), "XIV");
+        assert_eq!(to_roman(1990), "MCMXC");
+
+        let dir = std::env::temp_dir().join(format!("shop-automation-planner-plans-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("3 Day Plan I.menu"), "").unwrap();
+        fs::write(dir.join("3 Day Plan II.menu"), "").unwrap();
+        assert_eq!(next_plan_path(&dir, 3), dir.join("3 Day Plan III.menu"));
+        assert_eq!(next_plan_path(&dir, 5), dir.join("5 Day Plan I.menu"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    const RULES: &str = "\
+days: 3
+servings: 2
+delivery_day: true
+meals:
+  - name: Breakfast
+    folders: [Breakfast]
+    repeat: true
+  - name: Dinner
+    folders: [Dinners]
+snacks: [apples]
+";
+
+    /// A recipes root with one breakfast and two dinners; returns the root and the db path
+    fn recipes(test: &str) -> (PathBuf, String) {
+        let root = std::env::temp_dir().join(format!("shop-automation-planner-{}-{}", test, std::process::id()));
+        fs::create_dir_all(root.join("Breakfast")).unwrap();
+        fs::create_dir_all(root.join("Dinners")).unwrap();
+        fs::create_dir_all(root.join("config")).unwrap();
+        fs::write(root.join("Breakfast").join("Porridge.cook"), "Cook @oats{100%g} in @milk{300%ml}.\n").unwrap();
+        fs::write(root.join("Dinners").join("Stew.cook"), "Simmer @beef{500%g}.\n").unwrap();
+        fs::write(root.join("Dinners").join("Curry.cook"), "Simmer @chickpeas{400%g}.\n").unwrap();
+        fs::write(root.join("config").join("plan-rules.yml"), RULES).unwrap();
+        let db_path = root.join("config").join("db").to_string_lossy().to_string();
+        (root, db_path)
+    }
+
+    fn generated(root: &Path, db_path: &str, name: &str, seed: u64) -> String {
+        let output = root.join(name);
+        generate(db_path, &root.join("config").join("plan-rules.yml"), Some(output.clone()), Some(seed)).unwrap();
+        fs::read_to_string(output).unwrap()
+    }
+
+    #[test]
+    fn delivery_day_has_dinner_only_and_the_last_dinner_is_left_out() {
+        let (root, db_path) = recipes("delivery");
+        let menu = generated(&root, &db_path, "plan.menu", 7);
+
+        let day = |n: u32| {
+            let start = menu.find(&format!("==Day {}==", n)).unwrap();
+            let end = menu[start..].find("\n==").map_or(menu.len(), |i| start + i);
+            menu[start..end].to_string()
+        };
+        assert!(day(0).contains("-- delivery around 4pm") && day(0).contains("Dinner:"));
+        assert!(!day(0).contains("Breakfast:"));
+        for n in 1..=3 {
+            assert!(day(n).contains("Breakfast:\n- @./Breakfast/Porridge{2%servings}"));
+        }
+        assert!(day(1).contains("Dinner:") && day(2).contains("Dinner:"));
+        assert!(!day(3).contains("Dinner:") && day(3).contains("-- dinner from a new order"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_plan() {
+        let (root, db_path) = recipes("seed");
+        let first = generated(&root, &db_path, "first.menu", 42);
+        let second = generated(&root, &db_path, "second.menu", 42);
+        assert_eq!(first, second);
+        // Both dinners are used before either repeats
+        assert_eq!(first, "\
+---
+servings: 2
+---
+
+==Day 0==
+
+-- delivery around 4pm
+
+Dinner:
+- @./Dinners/Stew{2%servings}
+
+==Day 1==
+
+Breakfast:
+- @./Breakfast/Porridge{2%servings}
+
+Dinner:
+- @./Dinners/Curry{2%servings}
+
+==Day 2==
+
+Breakfast:
+- @./Breakfast/Porridge{2%servings}
+
+Dinner:
+- @./Dinners/Curry{2%servings}
+
+==Day 3==
+
+Breakfast:
+- @./Breakfast/Porridge{2%servings}
+
+-- dinner from a new order
+
+== Snacks ==
+
+- @apples{}
+
+== Batch Prep ==
+
+-- nothing
+
+== Baby ==
+");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
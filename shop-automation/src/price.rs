@@ -0,0 +1,156 @@
+use crate::models::ProductOption;
+
+// Prices are stored as scraped: "€1.05", "€2.19/kg", "€0.25 each",
+// "€3.00 was €4.99", "€1.99+ €0.15 Deposit". These helpers turn them into numbers.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceUnit {
+    Kg,
+    Litre,
+    Each,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UnitPrice {
+    pub euros: f64,
+    pub unit: PriceUnit,
+}
+
+/// First euro amount in the text; "€3.00 was €4.99" is the current price €3.00
+pub fn parse_euros(text: &str) -> Option<f64> {
+    let start = text.find('€')? + '€'.len_utf8();
+    let number: String = text[start..]
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .filter(|c| *c != ',')
+        .collect();
+    number.parse().ok()
+}
+
+/// "€1.40/kg", "€2.10/l", "€0.01/ml", "€0.25 each"
+pub fn parse_unit_price(text: &str) -> Option<UnitPrice> {
+    let euros = parse_euros(text)?;
+    let lower = text.to_lowercase();
+
+    if lower.contains("/kg") {
+        Some(UnitPrice { euros, unit: PriceUnit::Kg })
+    } else if lower.contains("/ml") {
+        Some(UnitPrice { euros: euros * 1000.0, unit: PriceUnit::Litre })
+    } else if lower.contains("/l") {
+        Some(UnitPrice { euros, unit: PriceUnit::Litre })
+    } else if lower.contains("each") {
+        Some(UnitPrice { euros, unit: PriceUnit::Each })
+    } else {
+        None
+    }
+}
+
+/// Amount of a pack in the unit prices are quoted in: "750 g" -> 0.75 kg
+pub fn pack_amount(quantity: &str) -> Option<(f64, PriceUnit)> {
+    let quantity = quantity.trim().to_lowercase();
+    let (number, unit) = quantity.split_once(' ')?;
+    let amount: f64 = number.parse().ok()?;
+    convert(amount, unit)
+}
+
+/// Recipe amounts in the same units: `{100%g}` -> 0.1 kg, `{2%tbsp}` -> 0.03 l,
+/// `{3%large}` -> 3 each. Units like cloves or pinches can't be converted.
+pub fn convert(amount: f64, unit: &str) -> Option<(f64, PriceUnit)> {
+    match unit.trim().to_lowercase().as_str() {
+        "g" | "gram" | "grams" => Some((amount / 1000.0, PriceUnit::Kg)),
+        "kg" => Some((amount, PriceUnit::Kg)),
+        "oz" | "ounce" | "ounces" => Some((amount * 0.02835, PriceUnit::Kg)),
+        "lb" | "lbs" | "pound" | "pounds" => Some((amount * 0.4536, PriceUnit::Kg)),
+        "ml" => Some((amount / 1000.0, PriceUnit::Litre)),
+        "l" | "litre" | "litres" => Some((amount, PriceUnit::Litre)),
+        "tsp" | "teaspoon" | "teaspoons" => Some((amount * 0.005, PriceUnit::Litre)),
+        "tbsp" | "tablespoon" | "tablespoons" => Some((amount * 0.015, PriceUnit::Litre)),
+        "cup" | "cups" => Some((amount * 0.24, PriceUnit::Litre)),
+        "" | "piece" | "pieces" | "items" | "small" | "medium" | "large" | "whole" => Some((amount, PriceUnit::Each)),
+        _ => None,
+    }
+}
+
+/// What one pack costs at the till. Loose produce is priced per kg
+/// ("€2.19/kg" for a "1 kg" pack), so it's scaled by the pack size.
+pub fn pack_price(option: &ProductOption) -> Option<f64> {
+    if let Some(per_unit) = parse_unit_price(&option.price)
+        && per_unit.unit != PriceUnit::Each
+    {
+        let (amount, unit) = pack_amount(option.quantity.as_deref()?)?;
+        return (unit == per_unit.unit).then_some(per_unit.euros * amount);
+    }
+    parse_euros(&option.price)
+}
+
+pub fn unit_price(option: &ProductOption) -> Option<UnitPrice> {
+    parse_unit_price(&option.price_per_unit)
+}
+
+/// Cheapest first, as `shop --policy cheapest` and `cost` pick options: by unit
+/// price when every one has one in the same unit (€/kg vs €/kg), otherwise by
+/// what a pack costs. Unpriced last; ties keep their order.
+pub fn sort_cheapest_first<T>(items: &mut [T], unit_price: impl Fn(&T) -> Option<UnitPrice>, pack_price: impl Fn(&T) -> Option<f64>) {
+    let units: Vec<_> = items.iter().filter_map(|item| unit_price(item).map(|u| u.unit)).collect();
+    let comparable_units = units.len() == items.len() && units.windows(2).all(|w| w[0] == w[1]);
+    
+    let key = |item: &T| -> Option<f64> {
+        if comparable_units {
+            unit_price(item).map(|u| u.euros)
+        } else {
+            pack_price(item)
+        }
+    };
+    
+    items.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_current_price() {
+        assert_eq!(parse_euros("€1.05"), Some(1.05));
+        assert_eq!(parse_euros("€3.00 was €4.99"), Some(3.0));
+        assert_eq!(parse_euros("€1,299.00"), Some(1299.0));
+        assert_eq!(parse_euros("€1.99+ €0.15 Deposit"), Some(1.99));
+        assert_eq!(parse_euros("Price unavailable"), None);
+    }
+
+    #[test]
+    fn parses_unit_prices() {
+        let kg = parse_unit_price("€2.19/kg").unwrap();
+        assert_eq!((kg.euros, kg.unit), (2.19, PriceUnit::Kg));
+        let ml = parse_unit_price("€0.01/ml").unwrap();
+        assert_eq!((ml.euros, ml.unit), (10.0, PriceUnit::Litre));
+        let each = parse_unit_price("€0.25 each").unwrap();
+        assert_eq!((each.euros, each.unit), (0.25, PriceUnit::Each));
+        assert!(parse_unit_price("€1.05").is_none());
+    }
+
+    #[test]
+    fn sorts_by_unit_price_only_when_comparable() {
+        let unit = |euros: f64, unit: PriceUnit| Some(UnitPrice { euros, unit });
+        // (unit price, pack price)
+        let mut same_unit = [(unit(3.0, PriceUnit::Kg), Some(1.5)), (unit(2.0, PriceUnit::Kg), Some(2.0))];
+        sort_cheapest_first(&mut same_unit, |o| o.0, |o| o.1);
+        assert_eq!(same_unit[0].1, Some(2.0));
+
+        let mut mixed = [(unit(3.0, PriceUnit::Kg), Some(1.5)), (unit(0.5, PriceUnit::Each), Some(2.0)), (None, None)];
+        sort_cheapest_first(&mut mixed, |o| o.0, |o| o.1);
+        assert_eq!(mixed.map(|o| o.1), [Some(1.5), Some(2.0), None]);
+    }
+
+    #[test]
+    fn converts_pack_amounts() {
+        assert_eq!(pack_amount("750 g"), Some((0.75, PriceUnit::Kg)));
+        assert!(pack_amount("bunch").is_none());
+    }
+}
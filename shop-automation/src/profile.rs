@@ -0,0 +1,198 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::PathBuf;
+use crate::settings::{DeliveryPrefs, Settings};
+
+// Named profiles from `profiles:` in `config/shop-automation.yml`, one per
+// SuperValu account. Each keeps its session in its own directory and can
+// override the store, delivery preferences, budget and db path.
+
+pub const DEFAULT_DB_PATH: &str = "../config/db";
+
+/// The store in the db's product links
+pub const DEFAULT_STORE: &str = "404";
+
+/// Who we're shopping as
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// `None` for the default account
+    pub name: Option<String>,
+    /// SuperValu store id, the `rsid` in product URLs
+    pub store: String,
+    pub delivery: Option<DeliveryPrefs>,
+    /// Run Chrome on a persistent user-data dir instead of replaying cookies
+    pub chrome_profile: bool,
+}
+
+impl Profile {
+    /// `shop-automation/` in the local data dir, `shop-automation/profiles/<name>/` for a profile
+    pub fn data_dir(&self) -> PathBuf {
+        let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
+        path.push("shop-automation");
+        if let Some(name) = &self.name {
+            path.push("profiles");
+            path.push(name);
+        }
+        path
+    }
+
+    /// Create the data dir, before writing a session into it
+    pub fn create_data_dir(&self) -> Result<PathBuf> {
+        let path = self.data_dir();
+        fs::create_dir_all(&path).context(format!("Failed to create {:?}", path))?;
+        Ok(path)
+    }
+
+    pub fn cookie_path(&self) -> PathBuf {
+        self.data_dir().join("supervalu_cookies.json")
+    }
+
+    pub fn vault_path(&self) -> PathBuf {
+        self.data_dir().join("session.vault")
+    }
+
+    /// The Chrome user-data dir, when the profile uses one. It keeps
+    /// localStorage and IndexedDB too, where the site remembers the store
+    /// and delivery address.
+    pub fn chrome_dir(&self) -> Option<PathBuf> {
+        self.chrome_profile.then(|| self.data_dir().join("chrome"))
+    }
+
+    /// `SUPERVALU_EMAIL`, or `SUPERVALU_EMAIL_ALEX` for profile alex, so
+    /// `.env` can hold every account
+    pub fn env_var(&self, base: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{}_{}", base, name.to_uppercase().replace('-', "_")),
+            None => base.to_string(),
+        }
+    }
+
+    /// "profile 'alex'" or "default profile", for messages
+    pub fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("profile '{}'", name),
+            None => "default profile".to_string(),
+        }
+    }
+}
+
+/// Profile `name` with its overrides applied to the settings next to
+/// `db_path`. Returns the profile, the db path to use and the settings.
+pub fn resolve(name: Option<&str>, db_path: Option<String>) -> Result<(Profile, String, Settings)> {
+    let base_db_path = db_path.clone().unwrap_or_else(|| DEFAULT_DB_PATH.to_string());
+    let mut settings = Settings::load(&base_db_path)?;
+
+    let Some(name) = name else {
+        let profile = Profile {
+            name: None,
+            store: settings.store.clone().unwrap_or_else(|| DEFAULT_STORE.to_string()),
+            delivery: settings.delivery.clone(),
+            chrome_profile: settings.chrome_profile,
+        };
+        return Ok((profile, base_db_path, settings));
+    };
+
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        bail!("Profile names can't be empty or contain '/', '\\' or '.': {:?}", name);
+    }
+    let Some(overrides) = settings.profiles.get(name).cloned() else {
+        let known: Vec<&str> = settings.profiles.keys().map(String::as_str).collect();
+        bail!(
+            "No profile '{}' under `profiles:` in the settings (known: {})",
+            name,
+            if known.is_empty() { "none".to_string() } else { known.join(", ") }
+        );
+    };
+
+    // An explicit --db-path still wins over the profile's
+    let db_path = db_path.or(overrides.db_path).unwrap_or(base_db_path);
+    if let Some(policy) = overrides.option_policy {
+        settings.option_policy = policy;
+    }
+    settings.budget = overrides.budget.or(settings.budget);
+    settings.delivery = overrides.delivery.or(settings.delivery);
+    settings.store = overrides.store.or(settings.store);
+    settings.chrome_profile = overrides.chrome_profile.unwrap_or(settings.chrome_profile);
+
+    let profile = Profile {
+        name: Some(name.to_string()),
+        store: settings.store.clone().unwrap_or_else(|| DEFAULT_STORE.to_string()),
+        delivery: settings.delivery.clone(),
+        chrome_profile: settings.chrome_profile,
+    };
+    Ok((profile, db_path, settings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: &str = "\
+store: \"404\"
+budget: 120
+option_policy: cheapest
+delivery:
+  days: [saturday]
+profiles:
+  alex:
+    db_path: ../alex/db
+    store: \"412\"
+    delivery:
+      days: [friday]
+    chrome_profile: true
+";
+
+    /// A db path whose settings file holds `SETTINGS`
+    fn db_path(test: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("shop-automation-profile-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("shop-automation.yml"), SETTINGS).unwrap();
+        dir.join("db").to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn default_profile_uses_the_top_level_settings() {
+        let db = db_path("default");
+        let (profile, resolved_db, settings) = resolve(None, Some(db.clone())).unwrap();
+
+        assert_eq!(profile.name, None);
+        assert_eq!(profile.store, "404");
+        assert_eq!(profile.delivery.unwrap().days, ["saturday"]);
+        assert!(!profile.chrome_profile);
+        assert_eq!(resolved_db, db);
+        assert_eq!(settings.budget, Some(120.0));
+    }
+
+    #[test]
+    fn profile_overrides_what_it_sets_and_keeps_the_rest() {
+        let db = db_path("alex");
+        let (profile, resolved_db, settings) = resolve(Some("alex"), Some(db.clone())).unwrap();
+
+        assert_eq!(profile.store, "412");
+        assert_eq!(profile.delivery.unwrap().days, ["friday"]);
+        assert!(profile.chrome_profile);
+        // Not set by the profile
+        assert_eq!(settings.budget, Some(120.0));
+        assert_eq!(settings.option_policy, crate::settings::OptionPolicy::Cheapest);
+        // An explicit --db-path wins over the profile's
+        assert_eq!(resolved_db, db);
+    }
+
+    #[test]
+    fn refuses_unknown_and_unsafe_profile_names() {
+        let db = db_path("names");
+        assert!(resolve(Some("sam"), Some(db.clone())).is_err());
+        assert!(resolve(Some("../alex"), Some(db.clone())).is_err());
+        assert!(resolve(Some(""), Some(db)).is_err());
+    }
+
+    #[test]
+    fn data_dir_is_per_profile_and_not_created_by_reading_it() {
+        let profile = Profile { name: Some("shop-automation-test-unused".to_string()), store: DEFAULT_STORE.to_string(), delivery: None, chrome_profile: false };
+        let dir = profile.data_dir();
+
+        assert!(dir.ends_with("shop-automation/profiles/shop-automation-test-unused"));
+        assert!(!dir.exists());
+        assert_eq!(profile.cookie_path(), dir.join("supervalu_cookies.json"));
+    }
+}
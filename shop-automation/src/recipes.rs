@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Just enough Cooklang to find out what a recipe or menu needs:
+// `@salt`, `@olive oil{1%tbsp}`, `@./Sides/Mashed Potatoes{2%servings}`.
+
+/// References nested deeper than this are assumed to be a cycle
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Ingredient {
+    pub name: String,
+    /// `None` for `@salt` or `@salt{}`, and for amounts like `{to taste}`
+    pub amount: Option<f64>,
+    /// Text after `%`: "g", "tbsp", "servings", ...
+    pub unit: Option<String>,
+}
+
+impl Ingredient {
+    /// `@./Sides/Mashed Potatoes{}` points at another recipe rather than a product
+    pub fn is_recipe_reference(&self) -> bool {
+        self.name.starts_with("./") || self.name.starts_with("../")
+    }
+
+    fn scaled(mut self, factor: f64) -> Self {
+        self.amount = self.amount.map(|amount| amount * factor);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct Recipe {
+    pub ingredients: Vec<Ingredient>,
+    /// Front matter and `>> key: value` lines, lists joined with ", "
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Recipe {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read recipe {:?}", path))?;
+        let (front_matter, body) = split_front_matter(&text);
+
+        let mut metadata = parse_front_matter(front_matter);
+        let mut ingredients = Vec::new();
+        for line in strip_comments(body).lines() {
+            match line.trim_start().strip_prefix(">>") {
+                Some(entry) => {
+                    if let Some((key, value)) = entry.split_once(':') {
+                        metadata.insert(key.trim().to_lowercase(), value.trim().to_string());
+                    }
+                }
+                None => ingredients.extend(parse_line_ingredients(line)),
+            }
+        }
+
+        Ok(Self {
+            ingredients,
+            metadata,
+        })
+    }
+
+    /// "servings: 4", ">> servings: 2", "servings: 4-6" (first number)
+    pub fn servings(&self) -> Option<f64> {
+        let servings = self.metadata.get("servings")?;
+        parse_amount(servings.split(['-', ' ']).next()?)
+    }
+}
+
+/// Every product a recipe or menu needs, with `@./Other Recipe{...}`
+/// references replaced by that recipe's ingredients scaled to match:
+/// `{2%servings}` against the recipe's `servings`, `{1/2}` as a multiplier,
+/// `{}` as the whole recipe.
+pub fn expand_ingredients(root: &Path, path: &Path) -> Result<Vec<Ingredient>> {
+    expand(root, path, Recipe::load(path)?.ingredients)
+}
+
+/// Same for ingredients already parsed out of `path`, e.g. one line of a menu
+pub fn expand(root: &Path, path: &Path, ingredients: Vec<Ingredient>) -> Result<Vec<Ingredient>> {
+    let mut out = Vec::new();
+    expand_into(root, path, ingredients, 1.0, 0, &mut out)?;
+    Ok(out)
+}
+
+fn expand_into(root: &Path, path: &Path, ingredients: Vec<Ingredient>, factor: f64, depth: usize, out: &mut Vec<Ingredient>) -> Result<()> {
+    for ingredient in ingredients {
+        if !ingredient.is_recipe_reference() {
+            out.push(ingredient.scaled(factor));
+            continue;
+        }
+
+        let Some(reference) = resolve_reference(root, path, &ingredient.name) else {
+            println!("⚠️  {:?}: recipe {} not found", path, ingredient.name);
+            continue;
+        };
+        if depth >= MAX_REFERENCE_DEPTH {
+            println!("⚠️  {:?}: references nested too deeply at {}", path, ingredient.name);
+            continue;
+        }
+
+        let scale = match (ingredient.amount, ingredient.unit.as_deref()) {
+            (Some(amount), Some("servings" | "serving")) => {
+                let servings = Recipe::load(&reference)?.servings().unwrap_or(amount);
+                amount / servings
+            }
+            (Some(amount), _) => amount,
+            (None, _) => 1.0,
+        };
+        let nested = Recipe::load(&reference)?.ingredients;
+        expand_into(root, &reference, nested, factor * scale, depth + 1, out)?;
+    }
+
+    Ok(())
+}
+
+/// Recipe references are relative to the recipes root, or failing that to
+/// the file that mentions them
+pub fn resolve_reference(root: &Path, from: &Path, name: &str) -> Option<PathBuf> {
+    // Menus usually spell the extension out: `@./Dinners/Carbonara.cook{2}`
+    let file = format!("{}.cook", name.strip_suffix(".cook").unwrap_or(name));
+    let from_dir = from.parent().unwrap_or(root);
+    [root.join(&file), from_dir.join(&file)]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+/// Recipes live at the repo root, two levels above `config/db`
+pub fn recipes_root(db_path: &str) -> PathBuf {
+    Path::new(db_path)
+        .parent()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// All `.cook` and `.menu` files under the root, skipping hidden and tooling directories
+pub fn find_recipe_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                if name != "config" && name != "shop-automation" && name != "target" {
+                    dirs.push(path);
+                }
+            } else if matches!(path.extension().and_then(|e| e.to_str()), Some("cook") | Some("menu")) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+pub fn split_front_matter(text: &str) -> (&str, &str) {
+    if let Some(rest) = text.strip_prefix("---\n")
+        && let Some(end) = rest.find("\n---")
+    {
+        let after = &rest[end + 4..];
+        return (&rest[..end], after.strip_prefix('\n').unwrap_or(after));
+    }
+    ("", text)
+}
+
+pub fn parse_front_matter(front_matter: &str) -> BTreeMap<String, String> {
+    let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::from_str(front_matter) else {
+        return BTreeMap::new();
+    };
+
+    let text = |value: &serde_yaml::Value| match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+
+    mapping
+        .iter()
+        .filter_map(|(key, value)| {
+            let key = text(key)?.to_lowercase();
+            let value = match value {
+                serde_yaml::Value::Sequence(items) => items.iter().filter_map(text).collect::<Vec<_>>().join(", "),
+                other => text(other)?,
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// "2", "1.5", "1/2", "½", "2-3" (first number)
+pub fn parse_amount(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let text = text.split_once('-').map_or(text, |(first, _)| first.trim());
+
+    match text {
+        "½" => return Some(0.5),
+        "¼" => return Some(0.25),
+        "¾" => return Some(0.75),
+        "⅓" => return Some(1.0 / 3.0),
+        _ => {}
+    }
+
+    if let Some((numerator, denominator)) = text.split_once('/') {
+        let numerator: f64 = numerator.trim().parse().ok()?;
+        let denominator: f64 = denominator.trim().parse().ok()?;
+        return (denominator != 0.0).then(|| numerator / denominator);
+    }
+    text.parse().ok()
+}
+
+/// `{100%g}` -> (100, "g"), `{2}` -> (2, none), `{}` and `{to taste}` -> no amount
+fn parse_quantity(braces: &str) -> (Option<f64>, Option<String>) {
+    let (amount, unit) = match braces.split_once('%') {
+        Some((amount, unit)) => (amount, Some(unit.trim().to_string()).filter(|u| !u.is_empty())),
+        None => (braces, None),
+    };
+    let amount = parse_amount(amount);
+    (amount, amount.and(unit))
+}
+
+/// Drop `-- line comments` and `[- block comments -]`
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[-") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find("-]") {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out.lines()
+        .map(|line| match line.find("--") {
+            Some(pos) => &line[..pos],
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn parse_line_ingredients(line: &str) -> Vec<Ingredient> {
+    let mut ingredients = Vec::new();
+    let mut rest = line;
+
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+
+        // Multi-word names need braces: `@olive oil{1%tbsp}`. Look for a `{`
+        // before the next token and accept it only if the text in between
+        // reads like a name rather than a sentence.
+        let next_token = rest.find(['@', '#', '~']).unwrap_or(rest.len());
+        let multi_word = rest[..next_token].find('{').filter(|&brace| {
+            let candidate = &rest[..brace];
+            !candidate.is_empty()
+                && !candidate.contains(['.', ',', ';', ':', '(', ')', '!', '?'])
+                || candidate.starts_with("./")
+                || candidate.starts_with("../")
+        });
+
+        let (name, after) = match multi_word {
+            Some(brace) => (&rest[..brace], &rest[brace..]),
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+
+        // Only a `{...}` straight after the name belongs to it
+        let (amount, unit, after) = match after.strip_prefix('{').and_then(|a| a.split_once('}')) {
+            Some((braces, remaining)) => {
+                let (amount, unit) = parse_quantity(braces);
+                (amount, unit, remaining)
+            }
+            None => (None, None, after),
+        };
+
+        let name = name.trim();
+        if !name.is_empty() {
+            ingredients.push(Ingredient {
+                name: name.to_string(),
+                amount,
+                unit,
+            });
+        }
+        rest = after;
+    }
+
+    ingredients
+}
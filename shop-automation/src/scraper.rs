@@ -1,60 +1,273 @@
 use anyhow::{Context, Result};
-use headless_chrome::{Browser, LaunchOptions};
+use headless_chrome::{Browser, LaunchOptions, Tab};
 use std::path::Path;
 use std::fs;
+use std::sync::Arc;
 use std::time::Duration;
 use crate::models::{ProductOption, ShoppingData};
+use crate::names::{MatchKind, NameResolver};
 
 const SUPERVALU_BASE_URL: &str = "https://shop.supervalu.ie";
 
-pub async fn scrape_product(product_name: &str, db_path: &str, visible: bool) -> Result<()> {
-    // Check if shopping.yml already exists and has data for this product
-    let clean_name = product_name
-        .to_lowercase()
-        .replace(" ", "_")
-        .replace("/", "_")
-        .replace("\\", "_");
+/// Product pages look like `https://shop.supervalu.ie/sm/delivery/rsid/404/product/<slug>-id-<digits>`
+pub fn is_product_url(url: &str) -> bool {
+    let Some(rest) = url
+        .strip_prefix(SUPERVALU_BASE_URL)
+        .and_then(|r| r.strip_prefix("/sm/delivery/rsid/"))
+    else {
+        return false;
+    };
+    let Some((store_id, slug)) = rest.split_once("/product/") else {
+        return false;
+    };
+    let slug = slug.split(['?', '#']).next().unwrap_or_default();
+    let Some((name, id)) = slug.rsplit_once("-id-") else {
+        return false;
+    };
+
+    !store_id.is_empty()
+        && store_id.chars().all(|c| c.is_ascii_digit())
+        && !name.is_empty()
+        && !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_digit())
+}
+
+/// `url` pointed at `store`: swaps the number after `/rsid/`. Other URLs are returned as they are.
+pub fn with_store(url: &str, store: &str) -> String {
+    let Some((head, rest)) = url.split_once("/rsid/") else {
+        return url.to_string();
+    };
+    let Some((store_id, tail)) = rest.split_once('/') else {
+        return url.to_string();
+    };
+    if store_id.is_empty() || !store_id.chars().all(|c| c.is_ascii_digit()) || store_id == store {
+        return url.to_string();
+    }
+    format!("{}/rsid/{}/{}", head, store, tail)
+}
+
+pub async fn scrape_product(product_name: &str, db_path: &str, store: &str, visible: bool) -> Result<()> {
+    // Resolve the product to an existing db entry so variants like "onions"
+    // don't end up in a second directory next to "onion"
+    let resolver = NameResolver::load(db_path)?;
+    if let Some(resolved) = resolver.resolve(product_name)
+        && resolved.kind != MatchKind::Exact
+    {
+        println!("🔗 {} resolved to existing entry '{}' ({:?} match)", product_name, resolved.key, resolved.kind);
+    }
+    let clean_name = resolver.canonical_key(product_name);
     
-    let yaml_path = Path::new(db_path).join(&clean_name).join("shopping.yml");
+    let yaml_path = resolver.shopping_yaml_path(&clean_name);
     
     if yaml_path.exists() {
         // Check if the file has actual product data
-        if let Ok(content) = fs::read_to_string(&yaml_path) {
-            if let Ok(data) = serde_yaml::from_str::<ShoppingData>(&content) {
-                // Check if it has real data (any URL populated means it's ready)
-                let has_real_data = data.supervalu.values().any(|opt| !opt.url.is_empty());
+        if let Ok(content) = fs::read_to_string(&yaml_path)
+            && let Ok(data) = serde_yaml::from_str::<ShoppingData>(&content)
+        {
+            // Check if it has real data (any URL populated means it's ready)
+            let has_real_data = data.supervalu.values().any(|opt| !opt.url.is_empty());
+            
+            if has_real_data {
+                println!("⏭ Skipping {} - shopping.yml has URL data (manually added or scraped)", product_name);
+                return Ok(());
+            } else {
+                println!("🔄 Re-scraping {} - shopping.yml exists but URLs are empty", product_name);
+            }
+        }
+    }
+    
+    let products = search_products(product_name, store, visible)?;
+    
+    // Save to YAML file (even if empty)
+    save_to_yaml(product_name, &clean_name, products, db_path)?;
+    
+    Ok(())
+}
+
+/// `scrape --nutrition`: fills in the `nutrition_facts.yml` that `nutrition`
+/// reads, from the primary option's product page, with one browser for the whole run
+pub struct NutritionFetcher {
+    visible: bool,
+    /// Launched for the first product page
+    browser: Option<(Browser, Arc<Tab>)>,
+}
+
+impl NutritionFetcher {
+    pub fn new(visible: bool) -> Self {
+        Self { visible, browser: None }
+    }
+    
+    /// Fetch the facts for a db entry unless it has some already (hand-written facts win)
+    pub fn fetch_missing(&mut self, product_name: &str, db_path: &str) -> Result<()> {
+        let resolver = NameResolver::load(db_path)?;
+        let yaml_path = resolver.shopping_yaml_path(&resolver.canonical_key(product_name));
+        let Some(item_dir) = yaml_path.parent() else {
+            return Ok(());
+        };
+        if item_dir.join("nutrition_facts.yml").exists() || !yaml_path.exists() {
+            return Ok(());
+        }
+        let data = ShoppingData::load(&yaml_path)?;
+        let Some((_, primary)) = data.sorted_options()
+            .into_iter()
+            .find(|(_, opt)| !opt.url.is_empty() && opt.dead.is_none())
+        else {
+            return Ok(());
+        };
+        
+        let tab = match &self.browser {
+            Some((_, tab)) => tab.clone(),
+            None => {
+                let browser = launch_browser(self.visible)?;
+                let tab = browser.new_tab().context("Failed to create new tab")?;
+                self.browser.insert((browser, tab)).1.clone()
+            }
+        };
+        if !scrape_nutrition(&tab, &primary.url, item_dir)? {
+            println!("\x1b[33m⚠ No nutrition table on {}'s product page\x1b[0m", primary.name);
+        }
+        Ok(())
+    }
+}
+
+/// Per-100g (or 100ml) values in the layout `nutrition` reads
+#[derive(serde::Serialize)]
+struct NutritionFacts {
+    serving_size: String,
+    calories: String,
+    source: String,
+    timestamp: String,
+    macronutrients: Macronutrients,
+}
+
+#[derive(serde::Serialize)]
+struct Macronutrients {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fat: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protein: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    carbohydrate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sugars: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fiber: Option<String>,
+}
+
+/// Read the nutrition table on a product page and write `nutrition_facts.yml`
+/// into `item_dir`. False when the page has no per-100g table.
+fn scrape_nutrition(tab: &Tab, url: &str, item_dir: &Path) -> Result<bool> {
+    println!("🥗 Fetching nutrition from: {}", url);
+    tab.navigate_to(url)
+        .context("Failed to navigate to product page")?;
+    std::thread::sleep(Duration::from_secs(3));
+    accept_cookie_consent(tab);
+    let _ = tab.wait_for_element_with_custom_timeout("h1, [data-testid*='ProductName']", Duration::from_secs(10));
+    
+    // Nutrition sits in a table headed "per 100g" (sometimes behind a tab
+    // that only hides it); rows are "Energy", "Fat", "of which Sugars", ...
+    let nutrition_js = r#"
+        (() => {
+            const text = el => (el?.textContent || '').trim().replace(/\s+/g, ' ');
+            const number = t => {
+                const match = (t || '').replace(',', '.').match(/<?\s*(\d+(?:\.\d+)?)/);
+                return match ? match[1] : null;
+            };
+            
+            for (const table of document.querySelectorAll('table')) {
+                const rows = [...table.querySelectorAll('tr')].map(tr => [...tr.querySelectorAll('th, td')].map(text));
+                const header = rows.find(cells => cells.some(c => /100\s*(g|ml)/i.test(c)));
+                if (!header) continue;
+                const column = header.findIndex(c => /100\s*(g|ml)/i.test(c));
+                const per = /100\s*ml/i.test(header[column]) ? '100ml' : '100g';
                 
-                if has_real_data {
-                    println!("⏭ Skipping {} - shopping.yml has URL data (manually added or scraped)", product_name);
-                    return Ok(());
-                } else {
-                    println!("🔄 Re-scraping {} - shopping.yml exists but URLs are empty", product_name);
+                const facts = { per };
+                for (const cells of rows) {
+                    if (cells === header || cells.length <= column) continue;
+                    const label = cells[0].toLowerCase();
+                    const value = cells[column];
+                    if (label.includes('energy') || value.toLowerCase().includes('kcal')) {
+                        const kcal = value.match(/(\d+(?:[.,]\d+)?)\s*kcal/i) || (label.includes('kcal') ? [null, number(value)] : null);
+                        if (kcal && kcal[1] && !facts.calories) facts.calories = kcal[1].replace(',', '.');
+                    } else if (label.startsWith('fat')) {
+                        facts.fat = facts.fat || number(value);
+                    } else if (label.startsWith('protein')) {
+                        facts.protein = facts.protein || number(value);
+                    } else if (label.startsWith('carbohydrate')) {
+                        facts.carbohydrate = facts.carbohydrate || number(value);
+                    } else if (label.includes('sugars')) {
+                        facts.sugars = facts.sugars || number(value);
+                    } else if (label.startsWith('fibre') || label.startsWith('fiber')) {
+                        facts.fiber = facts.fiber || number(value);
+                    }
                 }
+                if (facts.calories) return JSON.stringify(facts);
             }
-        }
+            return JSON.stringify(null);
+        })()
+    "#;
+    
+    let result = tab.evaluate(nutrition_js, false)
+        .context("Failed to read the nutrition table")?;
+    let json_str = result.value
+        .as_ref()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Failed to convert result to string"))?;
+    
+    #[derive(serde::Deserialize)]
+    struct RawFacts {
+        per: String,
+        calories: String,
+        fat: Option<String>,
+        protein: Option<String>,
+        carbohydrate: Option<String>,
+        sugars: Option<String>,
+        fiber: Option<String>,
     }
     
-    // Launch browser with appropriate options
-    let launch_options = LaunchOptions {
-        headless: !visible,
-        sandbox: false,
-        enable_gpu: false,
-        enable_logging: visible,
-        idle_browser_timeout: Duration::from_secs(120),
-        window_size: Some((1920, 1080)),
-        ..Default::default()
+    let Some(raw) = serde_json::from_str::<Option<RawFacts>>(json_str)
+        .context("Failed to parse nutrition JSON")?
+    else {
+        return Ok(false);
+    };
+    
+    let grams = |value: Option<String>| value.map(|v| format!("{}g", v));
+    let facts = NutritionFacts {
+        serving_size: raw.per,
+        calories: format!("{}kcal", raw.calories),
+        source: url.to_string(),
+        timestamp: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        macronutrients: Macronutrients {
+            fat: grams(raw.fat),
+            protein: grams(raw.protein),
+            carbohydrate: grams(raw.carbohydrate),
+            sugars: grams(raw.sugars),
+            fiber: grams(raw.fiber),
+        },
     };
     
-    let browser = Browser::new(launch_options)
-        .context("Failed to launch Chrome browser. Please ensure Google Chrome or Chromium is installed. On macOS, you can install it via: brew install --cask google-chrome")?;
+    let path = item_dir.join("nutrition_facts.yml");
+    let yaml = serde_yaml::to_string(&facts)
+        .context("Failed to serialize to YAML")?;
+    fs::write(&path, yaml)
+        .context(format!("Failed to write file: {:?}", path))?;
+    println!("✓ Saved nutrition facts to: {:?}", path);
+    
+    Ok(true)
+}
+
+/// Search `store` and return the first few product cards
+pub fn search_products(product_name: &str, store: &str, visible: bool) -> Result<Vec<ProductOption>> {
+    let browser = launch_browser(visible)?;
     
     let tab = browser.new_tab()
         .context("Failed to create new tab")?;
     
     // Navigate to search page
     let search_url = format!(
-        "{}/sm/delivery/rsid/404/results?q={}",
+        "{}/sm/delivery/rsid/{}/results?q={}",
         SUPERVALU_BASE_URL,
+        store,
         urlencoding::encode(product_name)
     );
     
@@ -67,6 +280,178 @@ pub async fn scrape_product(product_name: &str, db_path: &str, visible: bool) ->
     std::thread::sleep(Duration::from_secs(3));
     
     // Handle cookie consent popup
+    accept_cookie_consent(&tab);
+    
+    // Wait for products to load - using the actual selector from the HTML
+    println!("🔍 Waiting for products to load...");
+    let _ = tab.wait_for_element_with_custom_timeout("article[data-testid*='ProductCardWrapper']", Duration::from_secs(10));
+    
+    // Additional wait for dynamic content
+    std::thread::sleep(Duration::from_secs(2));
+    
+    // Extract product information using SuperValu's actual selectors
+    let products = extract_products_supervalu(&tab)?;
+    
+    // Keep browser open for inspection in visible mode
+    if visible && products.is_empty() {
+        println!("🔍 No products found. Browser will stay open for 15 seconds for inspection...");
+        std::thread::sleep(Duration::from_secs(15));
+    }
+    
+    Ok(products)
+}
+
+/// Fetch name, price and pack size for a single product page URL
+pub async fn scrape_product_page(url: &str, visible: bool) -> Result<ProductOption> {
+    let browser = launch_browser(visible)?;
+    
+    let tab = browser.new_tab()
+        .context("Failed to create new tab")?;
+    
+    println!("🔗 Navigating to: {}", url);
+    tab.navigate_to(url)
+        .context("Failed to navigate to product page")?;
+    
+    println!("⏳ Waiting for page to load...");
+    std::thread::sleep(Duration::from_secs(3));
+    
+    accept_cookie_consent(&tab);
+    
+    let _ = tab.wait_for_element_with_custom_timeout("h1, [data-testid*='ProductName']", Duration::from_secs(10));
+    std::thread::sleep(Duration::from_secs(1));
+    
+    let mut product = extract_product_page(&tab)?;
+    product.url = url.to_string();
+    
+    if product.name.is_empty() {
+        anyhow::bail!("No product details found at {}", url);
+    }
+    
+    Ok(product)
+}
+
+/// What the store shows at a stored product URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlStatus {
+    Available,
+    OutOfStock,
+    NotFound,
+    RedirectedToSearch(String),
+    /// The page was neither a product nor a not-found view, e.g. a login or
+    /// cookie consent page; says nothing about the product
+    Unknown(String),
+}
+
+impl UrlStatus {
+    pub fn is_dead(&self) -> bool {
+        matches!(self, UrlStatus::NotFound | UrlStatus::RedirectedToSearch(_))
+    }
+}
+
+/// Visit a product URL in a new tab and classify it. Delisted products either
+/// render a "page not found" view or bounce to search results, which is
+/// different from a product page with a disabled add button. Anything else
+/// (login walls, consent pages, a page that didn't render) is `Unknown`.
+pub fn check_product_url(browser: &Browser, url: &str) -> Result<UrlStatus> {
+    let tab = browser.new_tab()
+        .context("Failed to create new tab")?;
+    
+    tab.navigate_to(url)
+        .context(format!("Failed to navigate to {}", url))?;
+    let _ = tab.wait_until_navigated();
+    std::thread::sleep(Duration::from_secs(3));
+    
+    let check_js = r#"
+        (() => {
+            const text = document.body ? document.body.innerText.toLowerCase() : '';
+            const title = document.title.toLowerCase();
+            
+            const notFound = title.includes('not found') || title.includes('404') ||
+                text.includes('page not found') ||
+                text.includes("we can't find") || text.includes('we cannot find') ||
+                text.includes('no longer available') || text.includes('product is unavailable');
+            
+            const addButton = 'button[aria-label*="Add to Trolley"], button[aria-label*="Add to Cart"], button[data-testid*="addToCart"]';
+            const stockLabel = '[class*="out-of-stock"], [class*="OutOfStock"], [aria-label*="Out of stock"]';
+            const hasProduct = !!document.querySelector(addButton + ', [data-testid*="ProductName"]');
+            
+            // Only look at the main product, not "you may also like" carousels:
+            // the nearest ancestor of its name that also holds its add button or stock label
+            const nameEl = document.querySelector('[data-testid*="ProductName"], h1');
+            let product = nameEl;
+            while (product && product !== document.body && !product.querySelector(addButton + ', ' + stockLabel)) {
+                product = product.parentElement;
+            }
+            if (!product || product === document.body) {
+                product = nameEl ? nameEl.parentElement : null;
+            }
+            const productText = product ? product.innerText.toLowerCase() : '';
+            const outOfStock = !!product && (!!product.querySelector(stockLabel) || productText.includes('out of stock'));
+            
+            return JSON.stringify({
+                href: window.location.href,
+                notFound: notFound,
+                outOfStock: outOfStock,
+                hasProduct: hasProduct
+            });
+        })()
+    "#;
+    
+    let result = tab.evaluate(check_js, false)
+        .context("Failed to inspect product page")?;
+    let _ = tab.close(false);
+    
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct PageCheck {
+        href: String,
+        not_found: bool,
+        out_of_stock: bool,
+        has_product: bool,
+    }
+    
+    let json_str = result.value
+        .as_ref()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Failed to convert result to string"))?;
+    let page: PageCheck = serde_json::from_str(json_str)
+        .context("Failed to parse page check JSON")?;
+    
+    if page.href.contains("/results") || page.href.contains("/search") {
+        return Ok(UrlStatus::RedirectedToSearch(page.href));
+    }
+    if !page.href.contains("/product/") {
+        return Ok(UrlStatus::Unknown(format!("landed on {}", page.href)));
+    }
+    if page.not_found && !page.has_product {
+        return Ok(UrlStatus::NotFound);
+    }
+    if page.out_of_stock {
+        return Ok(UrlStatus::OutOfStock);
+    }
+    if page.has_product {
+        return Ok(UrlStatus::Available);
+    }
+    Ok(UrlStatus::Unknown("no product or not-found message on the page".to_string()))
+}
+
+pub fn launch_browser(visible: bool) -> Result<Browser> {
+    // Launch browser with appropriate options
+    let launch_options = LaunchOptions {
+        headless: !visible,
+        sandbox: false,
+        enable_gpu: false,
+        enable_logging: visible,
+        idle_browser_timeout: Duration::from_secs(120),
+        window_size: Some((1920, 1080)),
+        ..Default::default()
+    };
+    
+    Browser::new(launch_options)
+        .context("Failed to launch Chrome browser. Please ensure Google Chrome or Chromium is installed. On macOS, you can install it via: brew install --cask google-chrome")
+}
+
+fn accept_cookie_consent(tab: &headless_chrome::Tab) {
     println!("🍪 Handling cookie consent...");
     let cookie_js = r#"
         (() => {
@@ -109,27 +494,6 @@ pub async fn scrape_product(product_name: &str, db_path: &str, visible: bool) ->
     
     let _ = tab.evaluate(cookie_js, false);
     std::thread::sleep(Duration::from_secs(2));
-    
-    // Wait for products to load - using the actual selector from the HTML
-    println!("🔍 Waiting for products to load...");
-    let _ = tab.wait_for_element_with_custom_timeout("article[data-testid*='ProductCardWrapper']", Duration::from_secs(10));
-    
-    // Additional wait for dynamic content
-    std::thread::sleep(Duration::from_secs(2));
-    
-    // Extract product information using SuperValu's actual selectors
-    let products = extract_products_supervalu(&tab)?;
-    
-    // Keep browser open for inspection in visible mode
-    if visible && products.is_empty() {
-        println!("🔍 No products found. Browser will stay open for 15 seconds for inspection...");
-        std::thread::sleep(Duration::from_secs(15));
-    }
-    
-    // Save to YAML file (even if empty)
-    save_to_yaml(product_name, products, db_path)?;
-    
-    Ok(())
 }
 
 fn extract_products_supervalu(tab: &headless_chrome::Tab) -> Result<Vec<ProductOption>> {
@@ -249,16 +613,53 @@ fn extract_products_supervalu(tab: &headless_chrome::Tab) -> Result<Vec<ProductO
     }
 }
 
-fn save_to_yaml(product_name: &str, products: Vec<ProductOption>, db_path: &str) -> Result<()> {
-    // Clean product name for directory
-    let clean_name = product_name
-        .to_lowercase()
-        .replace(" ", "_")
-        .replace("/", "_")
-        .replace("\\", "_");
+fn extract_product_page(tab: &headless_chrome::Tab) -> Result<ProductOption> {
+    let js_code = r#"
+        (() => {
+            const text = (selector) => {
+                const el = document.querySelector(selector);
+                return el ? el.textContent.trim() : '';
+            };
+            
+            let fullName = text('[data-testid*="ProductName"], h1[class*="ProductDetails"], h1');
+            
+            // Same "(750 g)" suffix convention as the product cards
+            let name = fullName;
+            let quantity = null;
+            const quantityMatch = fullName.match(/\(([^)]+)\)$/);
+            if (quantityMatch) {
+                quantity = quantityMatch[1];
+                name = fullName.replace(/\s*\([^)]+\)$/, '').trim();
+            }
+            
+            const price = text('[data-testid*="productDetailsPrice"], [class*="ProductDetailsPrice"], [class*="ProductPrice"]');
+            const unitPrice = text('[class*="PriceInfo"], [class*="PricePerUnit"], [data-testid*="pricePerUnit"]');
+            
+            return JSON.stringify({
+                name: name,
+                url: window.location.href,
+                price: price || 'Price not available',
+                price_per_unit: unitPrice || price || '',
+                quantity: quantity
+            });
+        })()
+    "#;
+    
+    let result = tab.evaluate(js_code, false)
+        .context("Failed to extract product details from page")?;
     
+    let json_str = result.value
+        .as_ref()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Failed to convert result to string"))?;
+    
+    serde_json::from_str(json_str)
+        .context("Failed to parse product JSON")
+}
+
+fn save_to_yaml(product_name: &str, clean_name: &str, products: Vec<ProductOption>, db_path: &str) -> Result<()> {
     // Create directory path
-    let dir_path = Path::new(db_path).join(&clean_name);
+    let dir_path = Path::new(db_path).join(clean_name);
     fs::create_dir_all(&dir_path)
         .context(format!("Failed to create directory: {:?}", dir_path))?;
     
@@ -274,6 +675,8 @@ fn save_to_yaml(product_name: &str, products: Vec<ProductOption>, db_path: &str)
             price: String::new(),
             price_per_unit: String::new(),
             quantity: None,
+            dead: None,
+            extra: Default::default(),
         };
         shopping_data.add_option(1, empty_product);
     } else {
@@ -284,11 +687,7 @@ fn save_to_yaml(product_name: &str, products: Vec<ProductOption>, db_path: &str)
     
     // Write YAML file
     let yaml_path = dir_path.join("shopping.yml");
-    let yaml_content = serde_yaml::to_string(&shopping_data)
-        .context("Failed to serialize to YAML")?;
-    
-    fs::write(&yaml_path, yaml_content)
-        .context(format!("Failed to write file: {:?}", yaml_path))?;
+    shopping_data.save(&yaml_path)?;
     
     if is_empty {
         // Print in red using ANSI escape codes
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Household preferences for shop-automation, kept in `config/shop-automation.yml`
+// next to the db so they travel with the recipes.
+
+/// Order in which `shop` tries the stored options of an item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptionPolicy {
+    /// opt_1, opt_2, ... as curated with `db promote`
+    #[default]
+    InOrder,
+    /// Cheapest first, by unit price when comparable, otherwise by pack price
+    Cheapest,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub option_policy: OptionPolicy,
+    /// Spending cap in euros for `shop`, overridden by `--budget`
+    #[serde(default)]
+    pub budget: Option<f64>,
+    /// Slot `shop` books by itself; without it you pick one in the browser
+    #[serde(default)]
+    pub delivery: Option<DeliveryPrefs>,
+    /// SuperValu store id (the `rsid` in product links), 404 when missing
+    #[serde(default)]
+    pub store: Option<String>,
+    /// Keep the whole browser profile instead of a cookie file, like `--chrome-profile`
+    #[serde(default)]
+    pub chrome_profile: bool,
+    /// Named accounts for `--profile`
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileSettings>,
+}
+
+/// What a profile changes; anything left out comes from the top level
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileSettings {
+    #[serde(default)]
+    pub db_path: Option<String>,
+    #[serde(default)]
+    pub store: Option<String>,
+    #[serde(default)]
+    pub option_policy: Option<OptionPolicy>,
+    #[serde(default)]
+    pub budget: Option<f64>,
+    #[serde(default)]
+    pub delivery: Option<DeliveryPrefs>,
+    #[serde(default)]
+    pub chrome_profile: Option<bool>,
+}
+
+/// Which delivery slots are acceptable
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeliveryPrefs {
+    /// Weekdays, e.g. [saturday, sun]; any day when empty
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// Earliest start, e.g. "16:00" or "4pm"
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Latest end
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Most we'll pay for delivery, in euros
+    #[serde(default)]
+    pub max_fee: Option<f64>,
+}
+
+pub fn settings_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("shop-automation.yml")
+}
+
+impl Settings {
+    /// Missing file means defaults
+    pub fn load(db_path: &str) -> Result<Self> {
+        let path = settings_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .context(format!("Failed to read settings from {:?}", path))?;
+        // A file with only comments parses as null
+        let settings: Option<Self> = serde_yaml::from_str(&content)
+            .context(format!("Failed to parse settings in {:?}", path))?;
+        Ok(settings.unwrap_or_default())
+    }
+}
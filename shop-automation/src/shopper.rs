@@ -1,15 +1,38 @@
 use anyhow::{Context, Result, bail};
-use headless_chrome::{Browser, LaunchOptions, Tab, protocol::cdp::Network};
+use headless_chrome::{Browser, LaunchOptions, Tab};
+use headless_chrome::protocol::cdp::Network;
 use std::time::Duration;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
-use std::io;
-use crate::models::{ShoppingList, ShoppingItem};
+use std::sync::Arc;
+use crate::cart;
+use crate::models::{ShoppingData, ShoppingList, ShoppingItem};
+use crate::names::{MatchKind, NameResolver, clean_name};
+use crate::price::{self, UnitPrice};
+use crate::delivery;
+use crate::profile::Profile;
+use crate::scraper;
+use crate::settings::OptionPolicy;
+use crate::substitutions::SubstitutionRules;
+use crate::vault;
 use serde::{Serialize, Deserialize};
 
 const SUPERVALU_BASE_URL: &str = "https://shop.supervalu.ie";
 
+/// How long a submitted login may take to redirect back to the shop
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long someone at the keyboard has to finish a captcha in the window
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Verification codes asked for before giving up
+const MAX_CODE_ATTEMPTS: u32 = 3;
+
+/// Times to look at a product's stepper, a quarter second apart, for it to
+/// show a press
+const STEP_POLLS: u32 = 20;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Cookie {
     name: String,
@@ -24,15 +47,170 @@ struct Cookie {
     same_site: Option<String>,
 }
 
-fn get_cookie_file_path() -> PathBuf {
-    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push("shop-automation");
-    fs::create_dir_all(&path).ok();
-    path.push("supervalu_cookies.json");
-    path
+impl Cookie {
+    /// Session cookies (and those saved with expiry -1) never count as expired
+    fn is_expired(&self, now: f64) -> bool {
+        !self.session && self.expires.is_some_and(|expires| expires > 0.0 && expires < now)
+    }
+    
+    /// The DevTools form, which unlike `document.cookie` can set httpOnly
+    /// cookies and keeps the expiry
+    fn to_param(&self) -> Network::CookieParam {
+        let same_site = match self.same_site.as_deref() {
+            Some("Strict") => Some(Network::CookieSameSite::Strict),
+            Some("Lax") => Some(Network::CookieSameSite::Lax),
+            Some("None") => Some(Network::CookieSameSite::None),
+            _ => None,
+        };
+        
+        Network::CookieParam {
+            name: self.name.clone(),
+            value: self.value.clone(),
+            url: None,
+            domain: Some(self.domain.clone()),
+            path: Some(self.path.clone()),
+            secure: Some(self.secure),
+            http_only: Some(self.http_only),
+            same_site,
+            expires: self.expires.filter(|_| !self.session),
+            priority: None,
+            same_party: None,
+            source_scheme: None,
+            source_port: None,
+            partition_key: None,
+        }
+    }
+}
+
+impl From<Network::Cookie> for Cookie {
+    fn from(c: Network::Cookie) -> Self {
+        Cookie {
+            name: c.name,
+            value: c.value,
+            domain: c.domain,
+            path: c.path,
+            expires: Some(c.expires),
+            size: c.size,
+            http_only: c.http_only,
+            secure: c.secure,
+            session: c.session,
+            same_site: c.same_site.map(|s| format!("{:?}", s)),
+        }
+    }
+}
+
+/// Saved cookies from the vault when there is one, otherwise the plain file
+fn read_saved_cookies(profile: &Profile) -> Result<Option<Vec<Cookie>>> {
+    let vault_path = profile.vault_path();
+    if vault_path.exists() {
+        let secrets = vault::load(&vault_path)?;
+        let cookies = secrets.cookies
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<Cookie>, _>>()?;
+        return Ok(Some(cookies));
+    }
+    
+    let cookie_path = profile.cookie_path();
+    if !cookie_path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(&cookie_path)?;
+    Ok(Some(serde_json::from_str(&json)?))
+}
+
+/// Save cookies where `read_saved_cookies` finds them; `encrypt` moves a plain
+/// cookie file into a new vault. Returns where they went.
+fn write_saved_cookies(profile: &Profile, cookies: &[Cookie], encrypt: bool) -> Result<PathBuf> {
+    profile.create_data_dir()?;
+    let vault_path = profile.vault_path();
+    if encrypt || vault_path.exists() {
+        let mut secrets = if vault_path.exists() { vault::load(&vault_path)? } else { vault::Secrets::default() };
+        secrets.cookies = cookies.iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
+        vault::save(&vault_path, &secrets)?;
+        
+        // Don't leave a plaintext copy behind
+        let cookie_path = profile.cookie_path();
+        if cookie_path.exists() {
+            fs::remove_file(&cookie_path)
+                .context(format!("Failed to remove plaintext cookies {:?}", cookie_path))?;
+        }
+        return Ok(vault_path);
+    }
+    
+    let cookie_path = profile.cookie_path();
+    vault::write_private(&cookie_path, serde_json::to_string_pretty(cookies)?.as_bytes())?;
+    Ok(cookie_path)
+}
+
+/// Keep the account password in the vault, for logins without `.env`
+fn save_password_in_vault(profile: &Profile) -> Result<()> {
+    let email = match env::var(profile.env_var("SUPERVALU_EMAIL")) {
+        Ok(email) => email,
+        Err(_) => prompt_line("📧 SuperValu email: ")?,
+    };
+    let password = match env::var(profile.env_var("SUPERVALU_PASSWORD")) {
+        Ok(password) => password,
+        Err(_) => rpassword::prompt_password("🔑 SuperValu password: ")?,
+    };
+    
+    profile.create_data_dir()?;
+    let vault_path = profile.vault_path();
+    let mut secrets = if vault_path.exists() { vault::load(&vault_path)? } else { vault::Secrets::default() };
+    secrets.email = Some(email);
+    secrets.password = Some(password);
+    vault::save(&vault_path, &secrets)?;
+    println!("🔒 Credentials saved in {:?}; you can remove them from .env", vault_path);
+    Ok(())
+}
+
+/// One line from the terminal, even when stdin carried the shopping list
+fn prompt_line(prompt: &str) -> Result<String> {
+    use std::io::Write;
+    
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    #[cfg(unix)]
+    {
+        use std::io::{BufRead, BufReader};
+        BufReader::new(fs::File::open("/dev/tty")?).read_line(&mut input)?;
+    }
+    #[cfg(not(unix))]
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Chrome won't share a user-data dir between two running browsers
+fn launch(launch_options: LaunchOptions) -> Result<Browser> {
+    let chrome_dir = launch_options.user_data_dir.clone();
+    if let Some(dir) = &chrome_dir {
+        fs::create_dir_all(dir).context(format!("Failed to create the Chrome profile dir {:?}", dir))?;
+    }
+    Browser::new(launch_options).map_err(|e| match chrome_dir {
+        Some(dir) => anyhow::anyhow!(
+            "Failed to launch Chrome on {:?} (is another shop-automation run or Chrome using it?): {}",
+            dir, e
+        ),
+        None => e.context("Failed to launch Chrome browser"),
+    })
+}
+
+/// Where the session went, for the login messages
+fn save_session(tab: &Tab, profile: &Profile, encrypt: bool) -> Result<PathBuf> {
+    if let Some(chrome_dir) = profile.chrome_dir() {
+        // Chrome keeps the cookies and site storage in its own dir
+        return Ok(chrome_dir);
+    }
+    let cookies: Vec<Cookie> = tab.get_cookies()?.into_iter().map(Cookie::from).collect();
+    write_saved_cookies(profile, &cookies, encrypt)
 }
 
-pub fn manual_login_and_save_cookies() -> Result<()> {
+pub fn manual_login_and_save_cookies(profile: &Profile, encrypt: bool, save_password: bool) -> Result<()> {
+    if save_password {
+        save_password_in_vault(profile)?;
+    }
+    
     // Launch browser in visible mode
     let launch_options = LaunchOptions {
         headless: false,
@@ -41,11 +219,11 @@ pub fn manual_login_and_save_cookies() -> Result<()> {
         enable_logging: false,
         idle_browser_timeout: Duration::from_secs(600),
         window_size: Some((1920, 1080)),
+        user_data_dir: profile.chrome_dir(),
         ..Default::default()
     };
     
-    let browser = Browser::new(launch_options)
-        .context("Failed to launch Chrome browser")?;
+    let browser = launch(launch_options)?;
     
     let tab = browser.new_tab()
         .context("Failed to create new tab")?;
@@ -67,38 +245,22 @@ pub fn manual_login_and_save_cookies() -> Result<()> {
     
     // Check if logged in
     if !verify_logged_in(&tab)? {
-        println!("⚠️  You don't appear to be logged in. Let me save cookies anyway...");
-    }
-    
-    // Get all cookies
-    let cookies = tab.get_cookies()?;
-    
-    // Convert to our Cookie struct and save
-    let cookie_data: Vec<Cookie> = cookies.into_iter().map(|c| Cookie {
-        name: c.name,
-        value: c.value,
-        domain: c.domain,
-        path: c.path,
-        expires: Some(c.expires),
-        size: c.size,
-        http_only: c.http_only,
-        secure: c.secure,
-        session: c.session,
-        same_site: c.same_site.map(|s| format!("{:?}", s)),
-    }).collect();
-    
-    // Save cookies to file
-    let cookie_path = get_cookie_file_path();
-    let json = serde_json::to_string_pretty(&cookie_data)?;
-    fs::write(&cookie_path, json)?;
-    
-    println!("✅ Cookies saved to: {:?}", cookie_path);
+        println!("⚠️  You don't appear to be logged in. Let me save the session anyway...");
+    }
+    
+    let session_path = save_session(&tab, profile, encrypt || save_password)?;
+    
+    println!("✅ Session saved to: {:?}", session_path);
     println!("   You can now use the 'shop' command.");
     
     Ok(())
 }
 
-pub async fn login_and_save_cookies(visible: bool) -> Result<()> {
+pub async fn login_and_save_cookies(profile: &Profile, visible: bool, encrypt: bool, save_password: bool) -> Result<()> {
+    if save_password {
+        save_password_in_vault(profile)?;
+    }
+    
     // Launch browser
     let launch_options = LaunchOptions {
         headless: !visible,
@@ -107,11 +269,11 @@ pub async fn login_and_save_cookies(visible: bool) -> Result<()> {
         enable_logging: visible,
         idle_browser_timeout: Duration::from_secs(120),
         window_size: Some((1920, 1080)),
+        user_data_dir: profile.chrome_dir(),
         ..Default::default()
     };
     
-    let browser = Browser::new(launch_options)
-        .context("Failed to launch Chrome browser")?;
+    let browser = launch(launch_options)?;
     
     let tab = browser.new_tab()
         .context("Failed to create new tab")?;
@@ -124,31 +286,11 @@ pub async fn login_and_save_cookies(visible: bool) -> Result<()> {
     handle_cookies(&tab)?;
     
     // Perform login
-    login_to_supervalu(&tab)?;
-    
-    // Get all cookies
-    let cookies = tab.get_cookies()?;
-    
-    // Convert to our Cookie struct and save
-    let cookie_data: Vec<Cookie> = cookies.into_iter().map(|c| Cookie {
-        name: c.name,
-        value: c.value,
-        domain: c.domain,
-        path: c.path,
-        expires: Some(c.expires),
-        size: c.size,
-        http_only: c.http_only,
-        secure: c.secure,
-        session: c.session,
-        same_site: c.same_site.map(|s| format!("{:?}", s)),
-    }).collect();
-    
-    // Save cookies to file
-    let cookie_path = get_cookie_file_path();
-    let json = serde_json::to_string_pretty(&cookie_data)?;
-    fs::write(&cookie_path, json)?;
-    
-    println!("✅ Login successful! Cookies saved to: {:?}", cookie_path);
+    login_to_supervalu(&tab, profile)?;
+    
+    let session_path = save_session(&tab, profile, encrypt || save_password)?;
+    
+    println!("✅ Login successful! Session saved to: {:?}", session_path);
     println!("   You can now use the 'shop' command without logging in each time.");
     
     // Keep browser open briefly in visible mode
@@ -160,57 +302,47 @@ pub async fn login_and_save_cookies(visible: bool) -> Result<()> {
     Ok(())
 }
 
-pub async fn shop_items(shopping_list: ShoppingList, visible: bool, force_login: bool) -> Result<()> {
-    // Launch browser
-    let launch_options = LaunchOptions {
-        headless: !visible,
-        sandbox: false,
-        enable_gpu: false,
-        enable_logging: visible,
-        idle_browser_timeout: Duration::from_secs(300),
-        window_size: Some((1920, 1080)),
-        ..Default::default()
-    };
-    
-    let browser = Browser::new(launch_options)
-        .context("Failed to launch Chrome browser")?;
-    
-    let tab = browser.new_tab()
-        .context("Failed to create new tab")?;
-    
-    // Navigate to SuperValu
-    println!("🌐 Navigating to SuperValu...");
-    tab.navigate_to(SUPERVALU_BASE_URL)
-        .context("Failed to navigate to SuperValu")?;
-    
-    std::thread::sleep(Duration::from_secs(3));
-    
-    // Handle cookie consent
-    handle_cookies(&tab)?;
-    
-    // Load cookies or login
-    if force_login {
-        println!("🔐 Forcing fresh login...");
-        login_to_supervalu(&tab)?;
-        save_current_cookies(&tab)?;
-    } else if !load_and_set_cookies(&tab)? {
-        println!("🔐 No valid cookies found, logging in...");
-        login_to_supervalu(&tab)?;
-        save_current_cookies(&tab)?;
-    } else {
-        println!("🍪 Using saved cookies...");
-        // Verify we're logged in
-        if !verify_logged_in(&tab)? {
-            println!("⚠️  Saved cookies expired, logging in again...");
-            login_to_supervalu(&tab)?;
-            save_current_cookies(&tab)?;
-        } else {
-            println!("✅ Successfully restored session");
-        }
+/// How `shop` runs, from the command line and settings
+pub struct ShopOptions {
+    pub policy: OptionPolicy,
+    pub budget: Option<f64>,
+    pub visible: bool,
+    pub force_login: bool,
+    /// Make the cart match the list after adding
+    pub fix_cart: bool,
+}
+
+pub async fn shop_items(shopping_list: ShoppingList, db_path: &str, profile: &Profile, options: ShopOptions) -> Result<()> {
+    let ShopOptions { policy, budget, visible, force_login, fix_cart } = options;
+    
+    // Every product we could buy for each item: the list's own links plus all
+    // live db options, so e.g. `onions` can fall back to the red onion opt_3
+    let resolver = NameResolver::load(db_path)?;
+    let rules = SubstitutionRules::load(db_path)?;
+    let candidates: Vec<Vec<Candidate>> = shopping_list.items
+        .iter()
+        .map(|item| collect_candidates(item, &resolver, policy, &profile.store))
+        .collect();
+    
+    let (_browser, tab) = open_session(profile, visible, force_login)?;
+    
+    let mut report = ShopReport::default();
+    
+    // Book a delivery slot from the preferences, if there are any
+    if let Some(prefs) = &profile.delivery {
+        report.delivery_slot = match delivery::book_preferred(&tab, prefs) {
+            Ok(slot) => slot,
+            Err(e) => {
+                println!("   \x1b[33m⚠️  Couldn't book a delivery slot: {}\x1b[0m", e);
+                None
+            }
+        };
+        tab.navigate_to(SUPERVALU_BASE_URL)?;
+        std::thread::sleep(Duration::from_secs(2));
     }
     
-    // Pause for delivery slot selection
-    if visible {
+    // Otherwise pause for delivery slot selection
+    if visible && report.delivery_slot.is_none() {
         println!("\n📅 Please select your delivery slot in the browser.");
         println!("   Once you've selected a delivery slot, press Enter here to continue...");
         
@@ -229,41 +361,116 @@ pub async fn shop_items(shopping_list: ShoppingList, visible: bool, force_login:
         #[cfg(not(unix))]
         {
             let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            std::io::stdin().read_line(&mut input)?;
         }
         
         println!("✅ Starting to add items to cart...\n");
     }
     
-    // Add items to cart
-    let mut added_count = 0;
-    let mut failed_items: Vec<String> = Vec::new();
+    // Whatever an earlier session left in the cart counts against the budget
+    if let Some(cap) = budget {
+        let in_cart = match cart::read(&tab) {
+            Ok(cart) => cart.subtotal.unwrap_or_default(),
+            Err(e) => {
+                println!("\x1b[33m⚠️  Couldn't read the cart, starting the budget from €0: {}\x1b[0m", e);
+                0.0
+            }
+        };
+        report.budget = Budget::new(budget, in_cart);
+        println!("💶 Budget: €{:.2} (€{:.2} already in the cart)", cap, in_cart);
+    }
     
-    for (index, item) in shopping_list.items.iter().enumerate() {
+    // Add items to cart
+    for (index, (item, item_candidates)) in shopping_list.items.iter().zip(&candidates).enumerate() {
         println!("\n📦 [{}/{}] Processing: {}", index + 1, shopping_list.items.len(), item.name);
         if let Some(amount) = &item.amount {
             println!("   Amount needed: {}", amount);
         }
+        if let Some(note) = &item.note {
+            println!("   📝 {}", note);
+        }
+        
+        if !item.is_for_supervalu() {
+            let store = item.store.as_deref().unwrap_or_default();
+            println!("   🏪 Skipped - buy at {}", store);
+            report.other_stores.push(format!("{} ({})", item.name, store));
+            continue;
+        }
         
-        // Check if link is empty
-        if item.link.is_empty() {
-            println!("   \x1b[33m⏭️  Skipping - no link provided\x1b[0m");
-            failed_items.push(format!("{} (no link)", item.name));
+        if report.stopped_for_budget {
+            println!("   \x1b[33m💸 Skipped - budget reached\x1b[0m");
+            report.budget_skipped.push(item.name.clone());
             continue;
         }
         
-        match add_item_to_cart(&tab, item) {
-            Ok(true) => {
-                added_count += 1;
-                println!("   ✅ Added to cart");
+        // Check if there is anything to buy
+        let added = if item_candidates.is_empty() {
+            println!("   \x1b[33m⏭️  No link provided\x1b[0m");
+            Added::Unavailable
+        } else {
+            match add_item_to_cart(&tab, item, item_candidates, &report.budget) {
+                Ok(Added::Unavailable) => {
+                    println!("   ⚠️  Item might be out of stock");
+                    Added::Unavailable
+                }
+                Ok(added) => added,
+                Err(e) => {
+                    println!("   ❌ Failed: {}", e);
+                    Added::Unavailable
+                }
             }
-            Ok(false) => {
-                println!("   ⚠️  Item might be out of stock");
-                failed_items.push(item.name.clone());
+        };
+        
+        match added {
+            Added::Product(candidate, price) => {
+                report.budget.spend(price, item.packs());
+                if keep_within_budget(&tab, &mut report, item, &candidate.url, 0) {
+                    report.added_count += 1;
+                    report.expected.push(expected(item, &candidate.url, item_candidates));
+                    println!("   ✅ Added to cart");
+                }
             }
-            Err(e) => {
-                println!("   ❌ Failed: {}", e);
-                failed_items.push(item.name.clone());
+            Added::AlreadyInCart(candidate, price, had) => {
+                // Only the packs the run put on top count towards the budget
+                let had = had.unwrap_or_default();
+                report.budget.spend(price, item.packs().saturating_sub(had));
+                if keep_within_budget(&tab, &mut report, item, &candidate.url, had) {
+                    report.already_in_cart.push(item.name.clone());
+                    report.expected.push(expected(item, &candidate.url, item_candidates));
+                } else if had > 0 {
+                    report.expected.push(cart::Expected { packs: had, ..expected(item, &candidate.url, item_candidates) });
+                }
+            }
+            Added::OverBudget if item.is_optional() => {
+                println!("   \x1b[33m💸 Optional item dropped to stay within budget\x1b[0m");
+                report.budget_skipped.push(item.name.clone());
+            }
+            Added::OverBudget => {
+                println!("   \x1b[33m💸 Budget reached, not adding anything else\x1b[0m");
+                report.budget_skipped.push(item.name.clone());
+                report.stopped_for_budget = true;
+            }
+            Added::TooExpensive(max_price) => {
+                report.failed_items.push(format!("{} (over €{:.2} max price)", item.name, max_price));
+            }
+            Added::Unpriced => {
+                report.failed_items.push(format!("{} (no known price to check against the budget or max price)", item.name));
+            }
+            Added::Unavailable => {
+                if let Some((substitution, url, had)) = try_substitutes(&tab, item, &rules, &resolver, policy, &profile.store, &mut report.budget) {
+                    if keep_within_budget(&tab, &mut report, item, &url, had) {
+                        report.added_count += 1;
+                        println!("   ✅ Added substitute to cart");
+                        report.substitutions.push(substitution);
+                        report.expected.push(expected(item, &url, &[]));
+                    }
+                } else if let Some(first) = item_candidates.first() {
+                    report.failed_items.push(item.name.clone());
+                    // Still wanted, so the cart check lists it, but not for --fix-cart to retry
+                    report.expected.push(cart::Expected { unavailable: true, ..expected(item, &first.url, item_candidates) });
+                } else {
+                    report.failed_items.push(format!("{} (no link)", item.name));
+                }
             }
         }
         
@@ -271,9 +478,29 @@ pub async fn shop_items(shopping_list: ShoppingList, visible: bool, force_login:
         std::thread::sleep(Duration::from_secs(2));
     }
     
+    // Read the cart back and compare it with what was added; a cart page we
+    // can't read shouldn't hide the rest of the report
+    let mut cart = cart::read(&tab)
+        .inspect_err(|e| println!("\x1b[33m⚠️  Couldn't read the cart: {}\x1b[0m", e))
+        .ok();
+    if let Some(current) = &cart {
+        println!();
+        let diff = cart::diff(current, &report.expected);
+        cart::print_diff(&diff);
+        if fix_cart && diff.is_fixable() {
+            println!("\n🔧 Fixing the cart...");
+            report.unfixed = fix_cart_differences(&tab, &diff);
+            cart = cart::read(&tab).ok();
+            if let Some(fixed) = &cart {
+                cart::print_diff(&cart::diff(fixed, &report.expected));
+            }
+        } else if diff.is_fixable() {
+            println!("   Run with --fix-cart to make the cart match");
+        }
+    }
+    
     // Show cart summary
-    let failed_refs: Vec<&str> = failed_items.iter().map(|s| s.as_str()).collect();
-    show_cart_summary(&tab, added_count, &failed_refs)?;
+    show_cart_summary(&report, cart.as_ref());
     
     // Keep browser open for manual checkout
     if visible {
@@ -298,7 +525,7 @@ pub async fn shop_items(shopping_list: ShoppingList, visible: bool, force_login:
         #[cfg(not(unix))]
         {
             let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            std::io::stdin().read_line(&mut input)?;
         }
         
         println!("✅ Shopping session complete!");
@@ -307,33 +534,95 @@ pub async fn shop_items(shopping_list: ShoppingList, visible: bool, force_login:
     Ok(())
 }
 
-fn load_and_set_cookies(tab: &Tab) -> Result<bool> {
-    let cookie_path = get_cookie_file_path();
+/// A browser on SuperValu, logged in from the saved session (or the Chrome
+/// profile) or freshly when that has expired. Keep the `Browser` alive while
+/// using the tab.
+pub fn open_session(profile: &Profile, visible: bool, force_login: bool) -> Result<(Browser, Arc<Tab>)> {
+    // Launch browser
+    let launch_options = LaunchOptions {
+        headless: !visible,
+        sandbox: false,
+        enable_gpu: false,
+        enable_logging: visible,
+        idle_browser_timeout: Duration::from_secs(300),
+        window_size: Some((1920, 1080)),
+        user_data_dir: profile.chrome_dir(),
+        ..Default::default()
+    };
+    
+    let browser = launch(launch_options)?;
+    
+    let tab = browser.new_tab()
+        .context("Failed to create new tab")?;
+    
+    // Navigate to SuperValu
+    println!("🌐 Navigating to SuperValu...");
+    tab.navigate_to(SUPERVALU_BASE_URL)
+        .context("Failed to navigate to SuperValu")?;
+    
+    std::thread::sleep(Duration::from_secs(3));
+    
+    // Handle cookie consent
+    handle_cookies(&tab)?;
+    
+    // Load cookies or login
+    if profile.name.is_some() {
+        println!("👤 Using {} (store {})", profile.describe(), profile.store);
+    }
+    if force_login {
+        println!("🔐 Forcing fresh login...");
+        login_to_supervalu(&tab, profile)?;
+        save_current_cookies(&tab, profile)?;
+    } else if profile.chrome_profile {
+        // The browser profile restored everything when Chrome started
+        if !verify_logged_in(&tab)? {
+            println!("🔐 Not logged in in the Chrome profile, logging in...");
+            login_to_supervalu(&tab, profile)?;
+        } else {
+            println!("✅ Session restored from the Chrome profile");
+        }
+    } else if !load_and_set_cookies(&tab, profile)? {
+        println!("🔐 No valid cookies found, logging in...");
+        login_to_supervalu(&tab, profile)?;
+        save_current_cookies(&tab, profile)?;
+    } else {
+        println!("🍪 Using saved cookies...");
+        // Verify we're logged in
+        if !verify_logged_in(&tab)? {
+            println!("⚠️  Saved cookies expired, logging in again...");
+            login_to_supervalu(&tab, profile)?;
+            save_current_cookies(&tab, profile)?;
+        } else {
+            println!("✅ Successfully restored session");
+        }
+    }
+    
+    Ok((browser, tab))
+}
+
+fn load_and_set_cookies(tab: &Tab, profile: &Profile) -> Result<bool> {
+    let Some(cookies) = read_saved_cookies(profile)? else {
+        return Ok(false);
+    };
     
-    if !cookie_path.exists() {
+    // Drop expired cookies, from the file too so they aren't tried again
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    let saved = cookies.len();
+    let cookies: Vec<Cookie> = cookies.into_iter().filter(|c| !c.is_expired(now)).collect();
+    if cookies.len() < saved {
+        println!("🍪 Pruned {} expired cookies", saved - cookies.len());
+        write_saved_cookies(profile, &cookies, false)?;
+    }
+    if cookies.is_empty() {
         return Ok(false);
     }
     
-    let json = fs::read_to_string(&cookie_path)?;
-    let cookies: Vec<Cookie> = serde_json::from_str(&json)?;
-    
-    // Set each cookie
-    for cookie in cookies {
-        let js = format!(
-            r#"document.cookie = "{}={}; domain={}; path={}; {}{}";"#,
-            cookie.name,
-            cookie.value,
-            cookie.domain,
-            cookie.path,
-            if cookie.secure { "secure; " } else { "" },
-            if let Some(ref same_site) = cookie.same_site {
-                format!("SameSite={};", same_site)
-            } else {
-                String::new()
-            }
-        );
-        tab.evaluate(&js, false)?;
-    }
+    // Set them all through the DevTools network API, with every attribute
+    tab.set_cookies(cookies.iter().map(Cookie::to_param).collect())
+        .context("Failed to restore saved cookies")?;
     
     // Refresh page to apply cookies
     tab.reload(false, None)?;
@@ -342,47 +631,217 @@ fn load_and_set_cookies(tab: &Tab) -> Result<bool> {
     Ok(true)
 }
 
-fn save_current_cookies(tab: &Tab) -> Result<()> {
-    let cookies = tab.get_cookies()?;
-    
-    let cookie_data: Vec<Cookie> = cookies.into_iter().map(|c| Cookie {
-        name: c.name,
-        value: c.value,
-        domain: c.domain,
-        path: c.path,
-        expires: Some(c.expires),
-        size: c.size,
-        http_only: c.http_only,
-        secure: c.secure,
-        session: c.session,
-        same_site: c.same_site.map(|s| format!("{:?}", s)),
-    }).collect();
-    
-    let cookie_path = get_cookie_file_path();
-    let json = serde_json::to_string_pretty(&cookie_data)?;
-    fs::write(&cookie_path, json)?;
-    
+fn save_current_cookies(tab: &Tab, profile: &Profile) -> Result<()> {
+    save_session(tab, profile, false)?;
     Ok(())
 }
 
-fn verify_logged_in(tab: &Tab) -> Result<bool> {
+/// What the page says about the session
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginState {
+    logged_in: bool,
+    /// Name from the account menu or greeting, when the page shows one
+    account: Option<String>,
+    /// `rsid` the site has selected, from the URL or its links
+    store: Option<String>,
+}
+
+/// Logged in only on a positive signal: a greeting with the account name or a
+/// sign-out control. A visible "Sign in" always means logged out.
+fn check_login(tab: &Tab) -> Result<LoginState> {
     let check_login = r#"
         (() => {
-            // Check for indicators that we're logged in
-            const logoutBtn = document.querySelector('button[aria-label*="Log out"], a[href*="logout"]');
-            const userMenu = document.querySelector('[class*="user"], [class*="account"], [aria-label*="Account"]');
-            const signInBtn = document.querySelector('button[aria-label*="Sign in"], a[href*="login"]');
+            const visible = el => !!(el.offsetWidth || el.offsetHeight || el.getClientRects().length);
+            const text = el => (el.textContent || '').trim().replace(/\s+/g, ' ');
+            const controls = [...document.querySelectorAll('a, button')].filter(visible);
+            
+            const signIn = controls.some(el => /^(sign|log) ?in\b/i.test(text(el)) || /^(sign|log) ?in\b/i.test(el.getAttribute('aria-label') || ''));
+            const signOut = controls.some(el =>
+                /^(sign|log) ?out\b/i.test(text(el))
+                || /(logout|signout|log-out|sign-out)/i.test(el.getAttribute('href') || ''));
+            
+            // The account menu reads "Hi, Jane" once logged in
+            let account = null;
+            const accountElements = document.querySelectorAll(
+                '[data-testid*="account" i], [aria-label*="account" i], a[href*="/account"], button[aria-haspopup]'
+            );
+            for (const el of accountElements) {
+                const greeting = text(el).match(/^(?:hi|hello|welcome(?: back)?)[,!]?\s+([^,!]{1,40})/i);
+                if (greeting && visible(el)) {
+                    account = greeting[1].trim();
+                    break;
+                }
+            }
             
-            // If we see a sign in button, we're definitely not logged in
-            if (signInBtn) return false;
+            const loggedIn = !signIn && (account !== null || signOut);
+            
+            let store = (location.href.match(/\/rsid\/(\d+)/) || [])[1] || null;
+            if (!store) {
+                const link = document.querySelector('a[href*="/rsid/"]');
+                store = link ? (link.href.match(/\/rsid\/(\d+)/) || [])[1] || null : null;
+            }
             
-            // If we see logout or user menu, we're logged in
-            return !!(logoutBtn || userMenu);
+            return JSON.stringify({ loggedIn, account, store });
         })()
     "#;
     
     let result = tab.evaluate(check_login, false)?;
-    Ok(matches!(result.value, Some(serde_json::Value::Bool(true))))
+    match result.value {
+        Some(serde_json::Value::String(json)) => Ok(serde_json::from_str(&json)?),
+        _ => Ok(LoginState::default()),
+    }
+}
+
+/// Where a submitted login form has got to
+#[derive(Debug, Deserialize)]
+#[serde(tag = "state", content = "message", rename_all = "kebab-case")]
+enum LoginOutcome {
+    /// Still on the login page, no verdict yet
+    Pending,
+    /// Back on the shop after the post-login redirect
+    Redirected,
+    WrongPassword(String),
+    Captcha,
+    /// A verification code was asked for; the prompt text
+    Mfa(String),
+}
+
+fn login_outcome(tab: &Tab) -> Result<LoginOutcome> {
+    let outcome_js = r#"
+        (() => {
+            const visible = el => !!(el.offsetWidth || el.offsetHeight || el.getClientRects().length);
+            const text = el => (el.textContent || '').trim().replace(/\s+/g, ' ');
+            const result = (state, message) => JSON.stringify(message === undefined ? { state } : { state, message });
+            
+            // Challenge frames; the reCAPTCHA v3 badge is on every login page and doesn't count
+            const captcha = [...document.querySelectorAll(
+                'iframe[src*="recaptcha/api2/bframe"], iframe[src*="recaptcha/api2/anchor"], iframe[src*="hcaptcha"], iframe[title*="challenge" i], #px-captcha, [class*="captcha" i]:not(.grecaptcha-badge)'
+            )].filter(el => visible(el) && !el.closest('.grecaptcha-badge'));
+            if (captcha.length) return result('captcha');
+            
+            const codeInput = document.querySelector(
+                'input[autocomplete="one-time-code"], input[name*="otp" i], input[id*="otp" i], input[name*="verificationcode" i], input[id*="verificationcode" i], input[name="code"]'
+            );
+            const codeText = [...document.querySelectorAll('h1, h2, h3, p, label')]
+                .filter(visible)
+                .map(text)
+                .find(t => /verification code|one-time (pass)?code|security code|we('ve)? sent (you )?a code|enter the code/i.test(t));
+            if ((codeInput && visible(codeInput)) || codeText) return result('mfa', codeText || 'Enter the verification code');
+            
+            const error = [...document.querySelectorAll('[role="alert"], [aria-live="assertive"], [class*="error" i], [id*="error" i]')]
+                .filter(visible)
+                .map(text)
+                .find(t => /incorrect|invalid|wrong|doesn.t match|not recogni[sz]ed|couldn.t sign you in|try again/i.test(t));
+            if (error) return result('wrong-password', error);
+            
+            const onShop = location.hostname === 'shop.supervalu.ie' && !/\/(login|sign-?in)\b/i.test(location.pathname);
+            return result(onShop ? 'redirected' : 'pending');
+        })()
+    "#;
+    
+    let result = tab.evaluate(outcome_js, false)?;
+    match result.value {
+        Some(serde_json::Value::String(json)) => Ok(serde_json::from_str(&json)?),
+        _ => Ok(LoginOutcome::Pending),
+    }
+}
+
+fn verify_logged_in(tab: &Tab) -> Result<bool> {
+    Ok(check_login(tab)?.logged_in)
+}
+
+/// Print whether the saved session still logs in, for whom, when its cookies
+/// expire and which store is selected. Returns whether it's logged in.
+pub fn session_status(profile: &Profile, visible: bool) -> Result<bool> {
+    println!("🔐 Session for {}", profile.describe());
+    
+    let saved_cookies = if profile.chrome_profile {
+        println!("   Chrome profile: {:?}", profile.chrome_dir().unwrap_or_default());
+        None
+    } else {
+        let Some(cookies) = read_saved_cookies(profile)? else {
+            println!("❌ No saved session (run `login` first)");
+            return Ok(false);
+        };
+        Some(cookies)
+    };
+    
+    let launch_options = LaunchOptions {
+        headless: !visible,
+        sandbox: false,
+        enable_gpu: false,
+        enable_logging: visible,
+        idle_browser_timeout: Duration::from_secs(120),
+        window_size: Some((1920, 1080)),
+        user_data_dir: profile.chrome_dir(),
+        ..Default::default()
+    };
+    
+    let browser = launch(launch_options)?;
+    
+    let tab = browser.new_tab()
+        .context("Failed to create new tab")?;
+    
+    tab.navigate_to(SUPERVALU_BASE_URL)?;
+    std::thread::sleep(Duration::from_secs(3));
+    handle_cookies(&tab)?;
+    
+    let restored = saved_cookies.is_none() || load_and_set_cookies(&tab, profile)?;
+    let state = if restored { check_login(&tab)? } else { LoginState::default() };
+    
+    if state.logged_in {
+        match &state.account {
+            Some(account) => println!("✅ Logged in as {}", account),
+            None => println!("✅ Logged in (account name not shown on the page)"),
+        }
+    } else {
+        println!("❌ Not logged in: run `login` again");
+    }
+    
+    match &state.store {
+        Some(store) if *store != profile.store => println!(
+            "🏪 Store: {} \x1b[33m(configured: {})\x1b[0m",
+            store, profile.store
+        ),
+        Some(store) => println!("🏪 Store: {}", store),
+        None => println!("🏪 Store: not shown on the page (configured: {})", profile.store),
+    }
+    
+    // What the browser holds now, so expired cookies are already gone
+    let mut cookies: Vec<Cookie> = tab.get_cookies()?.into_iter().map(Cookie::from).collect();
+    cookies.sort_by(|a, b| {
+        let expiry = |c: &Cookie| if c.session { f64::MAX } else { c.expires.unwrap_or(f64::MAX) };
+        expiry(a).total_cmp(&expiry(b))
+    });
+    let (own, others): (Vec<&Cookie>, Vec<&Cookie>) = cookies.iter().partition(|c| c.domain.contains("supervalu"));
+    
+    println!("🍪 Cookies:");
+    let now = chrono::Utc::now();
+    for cookie in &own {
+        let expires = match cookie.expires {
+            Some(expires) if !cookie.session && expires > 0.0 => {
+                match chrono::DateTime::from_timestamp(expires as i64, 0) {
+                    Some(at) if at - now < chrono::Duration::days(1) => format!(
+                        "\x1b[33m{}\x1b[0m",
+                        at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                    ),
+                    Some(at) => at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+                    None => "?".to_string(),
+                }
+            }
+            _ => "end of browser session".to_string(),
+        };
+        println!("   {:<32} {:<24} {}", cookie.name, cookie.domain, expires);
+    }
+    if own.is_empty() {
+        println!("   (none for SuperValu)");
+    }
+    if !others.is_empty() {
+        println!("   + {} third-party cookies", others.len());
+    }
+    
+    Ok(state.logged_in)
 }
 
 fn handle_cookies(tab: &Tab) -> Result<()> {
@@ -427,21 +886,96 @@ fn handle_cookies(tab: &Tab) -> Result<()> {
     Ok(())
 }
 
-fn login_to_supervalu(tab: &Tab) -> Result<()> {
-    // Get credentials from environment
-    let email = env::var("SUPERVALU_EMAIL")
-        .context("SUPERVALU_EMAIL not found in environment. Please set it in .env file")?;
-    let password = env::var("SUPERVALU_PASSWORD")
-        .context("SUPERVALU_PASSWORD not found in environment. Please set it in .env file")?;
+fn login_to_supervalu(tab: &Tab, profile: &Profile) -> Result<()> {
+    // Get credentials from environment, or the vault
+    let (email_var, password_var) = (profile.env_var("SUPERVALU_EMAIL"), profile.env_var("SUPERVALU_PASSWORD"));
+    let (email, password) = match (env::var(&email_var), env::var(&password_var)) {
+        (Ok(email), Ok(password)) => (email, password),
+        _ if profile.vault_path().exists() => {
+            let secrets = vault::load(&profile.vault_path())?;
+            match (secrets.email, secrets.password) {
+                (Some(email), Some(password)) => (email, password),
+                _ => bail!("No credentials: set {} and {} in .env or run `login --save-password`", email_var, password_var),
+            }
+        }
+        _ => bail!("{} and {} not found in environment. Please set them in .env file or run `login --save-password`", email_var, password_var),
+    };
     
     println!("🔐 Logging in to SuperValu...");
     
-    // Navigate to login page
-    tab.navigate_to(&format!("{}/login", SUPERVALU_BASE_URL))?;
-    std::thread::sleep(Duration::from_secs(3));
+    open_login_form(tab)?;
+    fill_login_form(tab, &email, &password)?;
+    
+    // Wait for the redirect back to the shop, or for the page to say why not;
+    // captchas and verification codes are handed to whoever is at the terminal
+    let mut outcome = wait_for_login(tab, LOGIN_TIMEOUT)?;
+    let mut codes_tried = 0;
+    loop {
+        match outcome {
+            LoginOutcome::Redirected => break,
+            LoginOutcome::Pending => bail!(
+                "Login didn't finish within {}s, still on {}",
+                LOGIN_TIMEOUT.as_secs(),
+                tab.get_url()
+            ),
+            LoginOutcome::WrongPassword(message) => bail!(
+                "SuperValu rejected the credentials from {}/{} or the vault: {}",
+                email_var, password_var, message
+            ),
+            LoginOutcome::Captcha if !has_terminal() => {
+                bail!("SuperValu asked for a captcha and there's no terminal to hand it to; log in with `login --manual` instead")
+            }
+            LoginOutcome::Mfa(message) if !has_terminal() => bail!(
+                "SuperValu asked for a verification code ({}) and there's no terminal to ask; log in with `login --manual` instead",
+                message
+            ),
+            LoginOutcome::Mfa(message) => {
+                codes_tried += 1;
+                if codes_tried > MAX_CODE_ATTEMPTS {
+                    bail!("SuperValu didn't accept the verification code after {} tries", MAX_CODE_ATTEMPTS);
+                }
+                println!("🔢 SuperValu asks: {}", message);
+                let code = prompt_line("   Verification code: ")?;
+                if code.is_empty() {
+                    bail!("No verification code given");
+                }
+                enter_verification_code(tab, &code)?;
+                outcome = wait_for_login(tab, LOGIN_TIMEOUT)?;
+            }
+            LoginOutcome::Captcha if is_headless(tab) => {
+                finish_login_in_window(tab, &email, &password)?;
+                outcome = LoginOutcome::Redirected;
+            }
+            LoginOutcome::Captcha => {
+                prompt_line("🧩 Solve the captcha in the browser window, then press Enter here...")?;
+                outcome = wait_for_login(tab, LOGIN_TIMEOUT)?;
+            }
+        }
+    }
     
-    // Fill in login form
-    let login_js = format!(r#"
+    // The redirect alone isn't proof: the shop page has to show the account
+    let state = check_login(tab)?;
+    if !state.logged_in {
+        bail!("Login failed: back on {} but the page shows no signed-in account", tab.get_url());
+    }
+    match state.account {
+        Some(account) => println!("✅ Successfully logged in as {}", account),
+        None => println!("✅ Successfully logged in"),
+    }
+    
+    Ok(())
+}
+
+fn open_login_form(tab: &Tab) -> Result<()> {
+    // Navigate to login page, which may redirect to the sign-in form
+    tab.navigate_to(&format!("{}/login", SUPERVALU_BASE_URL))?;
+    tab.wait_for_element_with_custom_timeout("input[type=\"password\"]", Duration::from_secs(15))
+        .context(format!("No sign-in form at {}", tab.get_url()))?;
+    Ok(())
+}
+
+fn fill_login_form(tab: &Tab, email: &str, password: &str) -> Result<()> {
+    let login_js = format!(r#"
         (() => {{
             // Find email input
             const emailInput = document.querySelector('input[type="email"], input[name="email"], input[id*="email"], input[placeholder*="email"]');
@@ -472,47 +1006,586 @@ fn login_to_supervalu(tab: &Tab) -> Result<()> {
     "#, email.replace("'", "\\'"), password.replace("'", "\\'"));
     
     tab.evaluate(&login_js, false)?;
+    Ok(())
+}
+
+/// Poll until the login has an outcome; `Pending` when `timeout` runs out
+fn wait_for_login(tab: &Tab, timeout: Duration) -> Result<LoginOutcome> {
+    let started = std::time::Instant::now();
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+        match login_outcome(tab)? {
+            LoginOutcome::Pending if started.elapsed() < timeout => continue,
+            outcome => return Ok(outcome),
+        }
+    }
+}
+
+/// Whether someone can answer a prompt, even when stdin carried the shopping list
+fn has_terminal() -> bool {
+    #[cfg(unix)]
+    {
+        fs::File::open("/dev/tty").is_ok()
+    }
+    #[cfg(not(unix))]
+    {
+        use std::io::IsTerminal;
+        std::io::stdin().is_terminal()
+    }
+}
+
+fn is_headless(tab: &Tab) -> bool {
+    tab.evaluate("navigator.userAgent.includes('HeadlessChrome')", false)
+        .map(|result| matches!(result.value, Some(serde_json::Value::Bool(true))))
+        .unwrap_or(true)
+}
+
+/// Type the code into the verification form (one box, or one box per digit) and submit it
+fn enter_verification_code(tab: &Tab, code: &str) -> Result<()> {
+    let code_js = format!(r#"
+        (() => {{
+            const code = {};
+            const setValue = (input, value) => {{
+                const setter = Object.getOwnPropertyDescriptor(HTMLInputElement.prototype, 'value').set;
+                setter.call(input, value);
+                input.dispatchEvent(new Event('input', {{ bubbles: true }}));
+                input.dispatchEvent(new Event('change', {{ bubbles: true }}));
+            }};
+            
+            const digitBoxes = [...document.querySelectorAll('input[maxlength="1"]')];
+            if (digitBoxes.length >= code.length) {{
+                [...code].forEach((digit, i) => setValue(digitBoxes[i], digit));
+            }} else {{
+                const input = document.querySelector(
+                    'input[autocomplete="one-time-code"], input[name*="otp" i], input[id*="otp" i], input[name*="verificationcode" i], input[id*="verificationcode" i], input[name="code"], input[inputmode="numeric"]'
+                );
+                if (!input) return false;
+                setValue(input, code);
+            }}
+            
+            setTimeout(() => {{
+                const submit = document.querySelector('button[type="submit"]')
+                    || [...document.querySelectorAll('button')].find(b => /verify|continue|submit|confirm/i.test(b.textContent));
+                if (submit) submit.click();
+            }}, 300);
+            return true;
+        }})()
+    "#, serde_json::to_string(code)?);
+    
+    let result = tab.evaluate(&code_js, false)?;
+    if !matches!(result.value, Some(serde_json::Value::Bool(true))) {
+        bail!("Couldn't find where to enter the verification code on {}", tab.get_url());
+    }
+    Ok(())
+}
+
+/// A headless browser can't show a captcha, so sign in again in a visible
+/// window, let the person at the keyboard finish it there (captcha, code),
+/// and bring its cookies back into `tab`
+fn finish_login_in_window(tab: &Tab, email: &str, password: &str) -> Result<()> {
+    println!("🧩 SuperValu wants a captcha; opening a browser window to finish signing in...");
     
-    // Wait for login to complete
-    std::thread::sleep(Duration::from_secs(5));
+    let launch_options = LaunchOptions {
+        headless: false,
+        sandbox: false,
+        enable_gpu: false,
+        enable_logging: false,
+        idle_browser_timeout: HANDOFF_TIMEOUT + Duration::from_secs(60),
+        window_size: Some((1280, 900)),
+        ..Default::default()
+    };
+    let browser = launch(launch_options)?;
+    let window = browser.new_tab()
+        .context("Failed to create new tab")?;
     
-    // Check if login was successful
-    if verify_logged_in(&tab)? {
-        println!("✅ Successfully logged in");
-    } else {
-        bail!("Login failed. Please check your credentials.");
+    window.navigate_to(SUPERVALU_BASE_URL)?;
+    std::thread::sleep(Duration::from_secs(3));
+    handle_cookies(&window)?;
+    open_login_form(&window)?;
+    fill_login_form(&window, email, password)?;
+    
+    println!(
+        "   Solve the captcha (and enter any verification code) in the window; waiting up to {} minutes...",
+        HANDOFF_TIMEOUT.as_secs() / 60
+    );
+    let started = std::time::Instant::now();
+    loop {
+        match wait_for_login(&window, Duration::from_secs(2))? {
+            LoginOutcome::Redirected => break,
+            LoginOutcome::WrongPassword(message) => bail!("SuperValu rejected the credentials: {}", message),
+            _ if started.elapsed() < HANDOFF_TIMEOUT => continue,
+            _ => bail!("Sign-in in the browser window wasn't finished within {} minutes", HANDOFF_TIMEOUT.as_secs() / 60),
+        }
     }
     
+    let cookies: Vec<Network::CookieParam> = window.get_cookies()?
+        .into_iter()
+        .map(|cookie| Cookie::from(cookie).to_param())
+        .collect();
+    tab.set_cookies(cookies)?;
+    tab.navigate_to(SUPERVALU_BASE_URL)?;
+    tab.wait_until_navigated()?;
+    std::thread::sleep(Duration::from_secs(2));
+    println!("   Signed in in the window, carrying on headless");
     Ok(())
 }
 
-fn add_item_to_cart(tab: &Tab, item: &ShoppingItem) -> Result<bool> {
-    // Check if primary link is valid
-    if !item.link.is_empty() {
-        if add_product_by_url(tab, &item.link)? {
-            return Ok(true);
+/// Running total of the cart: the subtotal it had before the run, plus what
+/// this run added at the prices it was added at, corrected to the cart subtotal
+/// after each add. Under a cap, products with no known price aren't allowed:
+/// there is no telling whether they fit.
+#[derive(Default)]
+struct Budget {
+    cap: Option<f64>,
+    spent: f64,
+    /// Cart subtotal before the run
+    in_cart: f64,
+}
+
+/// What to do when the cart subtotal read back after an add is over the cap
+#[derive(Debug, PartialEq, Eq)]
+enum OverCap {
+    /// Take the optional item back out
+    Drop,
+    /// Keep it, but add nothing else
+    Stop,
+}
+
+impl Budget {
+    fn new(cap: Option<f64>, in_cart: f64) -> Self {
+        Self { cap, spent: in_cart, in_cart }
+    }
+    
+    /// Take the cart subtotal read back after adding `item` as the running total.
+    /// `None` while that's within the cap.
+    fn after_add(&mut self, subtotal: Option<f64>, item: &ShoppingItem) -> Option<OverCap> {
+        if let Some(subtotal) = subtotal {
+            self.spent = subtotal;
+        }
+        match self.cap {
+            Some(cap) if self.spent > cap + 0.005 => Some(if item.is_optional() { OverCap::Drop } else { OverCap::Stop }),
+            _ => None,
+        }
+    }
+    
+    fn allows(&self, price: Option<f64>, packs: u32) -> bool {
+        match (self.cap, price) {
+            (Some(cap), Some(price)) => self.spent + price * packs as f64 <= cap + 0.005,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+    
+    fn spend(&mut self, price: Option<f64>, packs: u32) {
+        self.spent += price.unwrap_or_default() * packs as f64;
+    }
+}
+
+/// With a budget, read the cart subtotal back after adding `item` (at `url`,
+/// `had` packs before the run). Over the cap, an optional item is put back to
+/// `had` packs and any other item stops the run. False when the item was taken out.
+fn keep_within_budget(tab: &Tab, report: &mut ShopReport, item: &ShoppingItem, url: &str, had: u32) -> bool {
+    if report.budget.cap.is_none() {
+        return true;
+    }
+    let read_subtotal = || match cart::read(tab) {
+        Ok(cart) => cart.subtotal,
+        Err(e) => {
+            println!("   \x1b[33m⚠️  Couldn't read the cart subtotal, going by the estimate: {}\x1b[0m", e);
+            None
+        }
+    };
+    
+    match report.budget.after_add(read_subtotal(), item) {
+        None => true,
+        Some(OverCap::Stop) => {
+            println!("   \x1b[33m💸 Cart is at €{:.2}, over the budget: not adding anything else\x1b[0m", report.budget.spent);
+            report.stopped_for_budget = true;
+            true
         }
+        Some(OverCap::Drop) => {
+            println!("   \x1b[33m💸 Cart is at €{:.2}, over the budget: taking the optional item back out\x1b[0m", report.budget.spent);
+            match change_cart_quantity(tab, url, had) {
+                Ok(true) => {
+                    report.budget.after_add(read_subtotal(), item);
+                }
+                Ok(false) => println!("   \x1b[33m⚠️  Couldn't take {} back out, do it in the cart\x1b[0m", item.name),
+                Err(e) => println!("   \x1b[33m⚠️  Couldn't take {} back out ({}), do it in the cart\x1b[0m", item.name, e),
+            }
+            report.budget_skipped.push(item.name.clone());
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+struct ShopReport {
+    added_count: usize,
+    failed_items: Vec<String>,
+    substitutions: Vec<String>,
+    budget_skipped: Vec<String>,
+    /// In the cart before the run; set to the list's packs, not counted as added
+    already_in_cart: Vec<String>,
+    other_stores: Vec<String>,
+    stopped_for_budget: bool,
+    budget: Budget,
+    /// Booked from the delivery preferences; `None` when picked by hand or not at all
+    delivery_slot: Option<String>,
+    /// What was added, to check the cart against
+    expected: Vec<cart::Expected>,
+    /// Cart differences `--fix-cart` couldn't fix
+    unfixed: Vec<String>,
+}
+
+/// What the cart should hold for an item: `url`, or another of its candidates
+fn expected(item: &ShoppingItem, url: &str, candidates: &[Candidate]) -> cart::Expected {
+    cart::Expected {
+        item: item.name.clone(),
+        url: url.to_string(),
+        packs: item.packs(),
+        alternatives: candidates
+            .iter()
+            .filter_map(|c| cart::product_id(&c.url))
+            .map(String::from)
+            .collect(),
+        unavailable: false,
+    }
+}
+
+/// A product page we can try for a shopping list item
+struct Candidate {
+    url: String,
+    label: String,
+    pack_price: Option<f64>,
+    unit_price: Option<UnitPrice>,
+}
+
+/// Product links point at `store`, whichever store the db was scraped from
+fn collect_candidates(item: &ShoppingItem, resolver: &NameResolver, policy: OptionPolicy, store: &str) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    
+    // Links from the list come first; they're opt_1/opt_2 when the template found the entry
+    for (label, url) in [("link", Some(&item.link)), ("backup link", item.backup_link.as_ref())] {
+        if let Some(url) = url
+            && !url.is_empty()
+        {
+            candidates.push(Candidate {
+                url: scraper::with_store(url, store),
+                label: label.to_string(),
+                pack_price: None,
+                unit_price: None,
+            });
+        }
+    }
+    
+    if let Some(resolved) = resolver.resolve(&item.name)
+        && let Ok(data) = ShoppingData::load(&resolver.shopping_yaml_path(&resolved.key))
+    {
+        if resolved.kind != MatchKind::Exact {
+            println!("🔗 {} resolved to db entry '{}' ({:?} match)", item.name, resolved.key, resolved.kind);
+        }
+        
+        for (key, option) in data.sorted_options() {
+            if option.url.is_empty() || option.dead.is_some() {
+                continue;
+            }
+            let label = format!("{}: {}", key, option.name);
+            let url = scraper::with_store(&option.url, store);
+            match candidates.iter_mut().find(|c| c.url == url) {
+                // Same product as a list link: keep its position, learn its price
+                Some(existing) => {
+                    existing.label = label;
+                    existing.pack_price = price::pack_price(option);
+                    existing.unit_price = price::unit_price(option);
+                }
+                None => candidates.push(Candidate {
+                    url,
+                    label,
+                    pack_price: price::pack_price(option),
+                    unit_price: price::unit_price(option),
+                }),
+            }
+        }
+    }
+    
+    if policy == OptionPolicy::Cheapest {
+        price::sort_cheapest_first(&mut candidates, |c| c.unit_price, |c| c.pack_price);
+    }
+    
+    candidates
+}
+
+/// Try the substitution rules for an item whose own options are exhausted.
+/// Returns a line for the report, e.g. "buffalo mozzarella -> mozzarella (opt_1: ...)",
+/// the product added and the packs of it the cart held before.
+fn try_substitutes(tab: &Tab, item: &ShoppingItem, rules: &SubstitutionRules, resolver: &NameResolver, policy: OptionPolicy, store: &str, budget: &mut Budget) -> Option<(String, String, u32)> {
+    let key = resolver.canonical_key(&item.name);
+    let names = [clean_name(&item.name), key];
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    
+    if rules.is_protected(&names, &item.tags) {
+        println!("   🚫 {} is never substituted", item.name);
+        return None;
+    }
+    
+    for substitute in rules.substitutes_for(&names, &item.tags) {
+        println!("   🔁 Trying substitute: {}", substitute);
+        // Same number of packs and price limit as the original
+        let substitute_item = ShoppingItem {
+            max_price: item.max_price,
+            quantity: item.quantity,
+            ..ShoppingItem::named(substitute)
+        };
+        let candidates = collect_candidates(&substitute_item, resolver, policy, store);
+        if candidates.is_empty() {
+            println!("   ⚠️  No db options for {}", substitute);
+            continue;
+        }
+        
+        let packs = substitute_item.packs();
+        let (candidate, price, had) = match add_item_to_cart(tab, &substitute_item, &candidates, budget) {
+            Ok(Added::Product(candidate, price)) => (candidate, price, 0),
+            Ok(Added::AlreadyInCart(candidate, price, had)) => (candidate, price, had.unwrap_or_default()),
+            _ => continue,
+        };
+        budget.spend(price, packs.saturating_sub(had));
+        return Some((format!("{} -> {} ({})", item.name, substitute, candidate.label), candidate.url.clone(), had));
     }
     
-    // If primary failed and we have a backup, try it
-    if let Some(backup_link) = &item.backup_link {
-        if !backup_link.is_empty() {
-            println!("   🔄 Primary product unavailable, trying backup...");
-            if add_product_by_url(tab, backup_link)? {
-                return Ok(true);
+    None
+}
+
+/// Result of walking an item's candidates
+enum Added<'a> {
+    /// Added at this price per pack, from the product page or else the db
+    Product(&'a Candidate, Option<f64>),
+    /// Was in the cart before the run with the packs shown then, and has been
+    /// set to the item's packs
+    AlreadyInCart(&'a Candidate, Option<f64>, Option<u32>),
+    /// Nothing was added and at least one candidate was passed over for the budget
+    OverBudget,
+    /// Nothing was added and at least one candidate cost more than the item's `max_price`
+    TooExpensive(f64),
+    /// Nothing was added and at least one candidate was passed over for having
+    /// no known price while a budget or `max_price` is set
+    Unpriced,
+    Unavailable,
+}
+
+/// Why a candidate's price rules it out
+enum PriceCheck {
+    Fits,
+    TooExpensive,
+    OverBudget,
+    Unpriced,
+}
+
+fn check_price(item: &ShoppingItem, label: &str, price: Option<f64>, budget: &Budget) -> PriceCheck {
+    if let Some(max_price) = item.max_price {
+        match price {
+            Some(price) if price > max_price + 0.005 => {
+                println!("   🚫 {} (€{:.2}) costs more than the €{:.2} max price", label, price, max_price);
+                return PriceCheck::TooExpensive;
+            }
+            Some(_) => {}
+            None => {
+                println!("   🚫 {} has no known price to check against the €{:.2} max price", label, max_price);
+                return PriceCheck::Unpriced;
+            }
+        }
+    }
+    
+    if !budget.allows(price, item.packs()) {
+        return match price {
+            Some(price) => {
+                println!("   💸 {} (€{:.2}) would exceed the budget", label, price * item.packs() as f64);
+                PriceCheck::OverBudget
+            }
+            None => {
+                println!("   💸 {} has no known price, skipped to stay within the budget", label);
+                PriceCheck::Unpriced
             }
+        };
+    }
+    
+    PriceCheck::Fits
+}
+
+fn add_item_to_cart<'a>(tab: &Tab, item: &ShoppingItem, candidates: &'a [Candidate], budget: &Budget) -> Result<Added<'a>> {
+    let mut over_budget = false;
+    let mut too_expensive = false;
+    let mut unpriced = false;
+    let mut passed_over = |check: PriceCheck| match check {
+        PriceCheck::Fits => false,
+        PriceCheck::TooExpensive => {
+            too_expensive = true;
+            true
+        }
+        PriceCheck::OverBudget => {
+            over_budget = true;
+            true
+        }
+        PriceCheck::Unpriced => {
+            unpriced = true;
+            true
+        }
+    };
+    
+    // Walk every option until one makes it into the cart
+    for (index, candidate) in candidates.iter().enumerate() {
+        // A db price that is already too high saves opening the page
+        if candidate.pack_price.is_some() && passed_over(check_price(item, &candidate.label, candidate.pack_price, budget)) {
+            continue;
+        }
+        
+        if index > 0 {
+            println!("   🔄 Previous product unavailable, trying {}...", candidate.label);
         } else {
-            println!("   ⚠️  Backup link is also empty");
+            println!("   🔗 Trying {}", candidate.label);
         }
+        
+        let page = match open_product_page(tab, &candidate.url) {
+            Ok(Some(page)) => page,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("   ❌ {}: {}", candidate.label, e);
+                continue;
+            }
+        };
+        
+        // What the store charges today beats what was scraped
+        let price = page.pack_price().or(candidate.pack_price);
+        if passed_over(check_price(item, &candidate.label, price, budget)) {
+            continue;
+        }
+        
+        match add_from_page(tab, &page) {
+            Ok(AddOutcome::Added) => {
+                if item.packs() > 1 {
+                    set_cart_quantity(tab, item.packs())?;
+                }
+                return Ok(Added::Product(candidate, price));
+            }
+            Ok(AddOutcome::InCart) => {
+                let had = page.packs();
+                if had != Some(item.packs()) {
+                    set_cart_quantity(tab, item.packs())?;
+                }
+                return Ok(Added::AlreadyInCart(candidate, price, had));
+            }
+            Ok(AddOutcome::NotAdded) => {}
+            Err(e) => println!("   ❌ {}: {}", candidate.label, e),
+        }
+    }
+    
+    Ok(if over_budget {
+        Added::OverBudget
+    } else if too_expensive {
+        Added::TooExpensive(item.max_price.unwrap_or_default())
+    } else if unpriced {
+        Added::Unpriced
+    } else {
+        Added::Unavailable
+    })
+}
+
+/// Finds the main product on a product page as `product`, so buttons and
+/// prices in "you may also like" carousels are never touched: the nearest
+/// ancestor of the product name that holds its add button or stepper.
+const PRODUCT_JS: &str = r#"
+    const text = el => (el?.textContent || '').trim().replace(/\s+/g, ' ');
+    const addButton = 'button[aria-label*="Add to Trolley" i], button[aria-label*="Add to Cart" i], button[data-testid*="addToCart" i], button[class*="AddToCart"]';
+    const increase = 'button[aria-label*="Increase" i], button[data-testid*="increment" i], button[class*="Increment"]';
+    const decrease = 'button[aria-label*="Decrease" i], button[data-testid*="decrement" i], button[class*="Decrement"], button[aria-label*="Remove" i]';
+    const quantityField = 'input[type="number"], input[aria-label*="quantity" i], [data-testid*="quantity" i]';
+    const stockLabel = '[class*="out-of-stock"], [class*="OutOfStock"], [aria-label*="Out of stock" i]';
+    const product = (() => {
+        const name = document.querySelector('[data-testid*="ProductName"], h1');
+        let el = name;
+        while (el && el !== document.body && !el.querySelector(addButton + ', ' + increase + ', ' + stockLabel)) {
+            el = el.parentElement;
+        }
+        return el && el !== document.body ? el : null;
+    })();
+"#;
+
+fn product_js(body: &str) -> String {
+    format!("(() => {{\n{}\n{}\n}})()", PRODUCT_JS, body)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PageState {
+    /// The add button is showing
+    CanAdd,
+    /// The stepper is showing, so the product is in the cart
+    InCart,
+    OutOfStock,
+    NotFound,
+}
+
+/// The main product on a product page
+#[derive(Debug, Deserialize)]
+struct ProductPage {
+    state: PageState,
+    /// The first euro amount on the product, without per-kg or "was" prices
+    price: String,
+    /// What the stepper shows
+    quantity: String,
+}
+
+impl ProductPage {
+    fn pack_price(&self) -> Option<f64> {
+        price::parse_euros(&self.price)
     }
     
-    Ok(false)
+    /// Packs in the cart; 0 when the add button is showing
+    fn packs(&self) -> Option<u32> {
+        match self.state {
+            PageState::CanAdd => Some(0),
+            PageState::InCart => self.quantity
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|part| !part.is_empty())
+                .and_then(|digits| digits.parse().ok()),
+            PageState::OutOfStock | PageState::NotFound => None,
+        }
+    }
 }
 
-fn add_product_by_url(tab: &Tab, url: &str) -> Result<bool> {
-    // Validate URL
+fn read_product_page(tab: &Tab) -> Result<ProductPage> {
+    let read_js = product_js(r#"
+        if (!product) {
+            return JSON.stringify({ state: 'not_found', price: '', quantity: '' });
+        }
+        const add = product.querySelector(addButton);
+        const field = product.querySelector(quantityField);
+        const price = [...product.querySelectorAll('*')]
+            .filter(el => el.children.length === 0 && text(el).includes('€'))
+            .map(text)
+            .find(t => !/\/\s*(kg|l|ml|g|100g|100ml)\b|was|save|deposit/i.test(t)) || '';
+        let state = 'not_found';
+        if (add && !add.disabled && text(add).toLowerCase().includes('add')) {
+            state = 'can_add';
+        } else if (product.querySelector(increase + ', ' + decrease)) {
+            state = 'in_cart';
+        } else if (product.querySelector(stockLabel) || text(product).toLowerCase().includes('out of stock')) {
+            state = 'out_of_stock';
+        }
+        return JSON.stringify({ state, price, quantity: field ? String(field.value || text(field)) : '' });
+    "#);
+    
+    let result = tab.evaluate(&read_js, false)?;
+    let json = result.value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .context("The product page didn't return anything")?;
+    serde_json::from_str(json).context("Failed to read the product page")
+}
+
+/// Navigate to a product and read it; `None` for a link that isn't a URL
+fn open_product_page(tab: &Tab, url: &str) -> Result<Option<ProductPage>> {
     if url.is_empty() {
-        return Ok(false);
+        return Ok(None);
     }
     
     // Ensure URL is complete
@@ -523,188 +1596,310 @@ fn add_product_by_url(tab: &Tab, url: &str) -> Result<bool> {
     } else {
         // Invalid URL format
         println!("   ⚠️  Invalid URL format: {}", url);
-        return Ok(false);
+        return Ok(None);
     };
     
-    // Navigate to product page
     tab.navigate_to(&full_url)?;
     std::thread::sleep(Duration::from_secs(3));
+    read_product_page(tab).map(Some)
+}
+
+/// Wait for the stepper to show something other than `previous`
+fn wait_for_packs(tab: &Tab, previous: Option<u32>) -> Result<Option<u32>> {
+    for _ in 0..STEP_POLLS {
+        std::thread::sleep(Duration::from_millis(250));
+        let packs = read_product_page(tab)?.packs();
+        if packs.is_some() && packs != previous {
+            return Ok(packs);
+        }
+    }
+    Ok(previous)
+}
+
+/// Press the product's plus or minus button once
+fn press_stepper(tab: &Tab, up: bool) -> Result<bool> {
+    let press_js = product_js(&format!(r#"
+        const btn = product && product.querySelector({});
+        if (!btn || btn.disabled) return false;
+        btn.click();
+        return true;
+    "#, if up { "increase" } else { "decrease" }));
+    
+    let result = tab.evaluate(&press_js, false)?;
+    Ok(matches!(result.value, Some(serde_json::Value::Bool(true))))
+}
+
+/// Step the product on the current page to `packs`, one press at a time,
+/// waiting for the stepper to catch up after each. True once the page shows `packs`.
+fn set_quantity(tab: &Tab, packs: u32) -> Result<bool> {
+    let mut current = read_product_page(tab)?.packs();
+    // Every press moves one pack; a few spare for presses the page ignores
+    for _ in 0..packs.abs_diff(current.unwrap_or_default()) + 3 {
+        let Some(shown) = current else {
+            return Ok(false);
+        };
+        if shown == packs {
+            return Ok(true);
+        }
+        if !press_stepper(tab, shown < packs)? {
+            return Ok(false);
+        }
+        current = wait_for_packs(tab, current)?;
+    }
+    Ok(current == Some(packs))
+}
+
+/// After "Add to Trolley" the button turns into a stepper showing 1;
+/// step it up until the cart holds `packs`
+fn set_cart_quantity(tab: &Tab, packs: u32) -> Result<()> {
+    wait_for_packs(tab, Some(0))?;
+    if set_quantity(tab, packs)? {
+        println!("   🔢 Quantity set to {}", packs);
+    } else {
+        let shown = read_product_page(tab)?.packs();
+        let shown = shown.map_or("an unknown number".to_string(), |packs| packs.to_string());
+        println!("   ⚠️  Could not set quantity to {} (the page shows {}), adjust it in the cart", packs, shown);
+    }
     
-    // Try to find and click "Add to Cart" button
-    let add_to_cart_js = r#"
-        (() => {
-            // Look for add to cart button with various selectors
-            const selectors = [
-                'button[aria-label*="Add to Trolley"]',
-                'button[aria-label*="Add to Cart"]',
-                'button[data-testid*="addToCart"]',
-                'button[class*="AddToCart"]',
-                'button:has-text("Add to Trolley")',
-                'button:has-text("Add to Cart")'
-            ];
-            
-            for (const selector of selectors) {
-                try {
-                    const btn = document.querySelector(selector);
-                    if (btn && !btn.disabled) {
-                        // Check if it's not already in cart
-                        const btnText = btn.textContent.toLowerCase();
-                        if (btnText.includes('add')) {
-                            btn.click();
-                            return 'added';
-                        } else if (btnText.includes('update') || btnText.includes('quantity')) {
-                            return 'already_in_cart';
-                        }
-                    }
-                } catch (e) {}
-            }
-            
-            // Check if item is out of stock
-            const outOfStock = document.querySelector('[class*="out-of-stock"], [class*="OutOfStock"], [aria-label*="Out of stock"]');
-            if (outOfStock) {
-                return 'out_of_stock';
+    Ok(())
+}
+
+/// Go to a cart line's product page and step it to `to` packs (0 takes it
+/// out of the cart). True once the page shows `to`.
+pub fn change_cart_quantity(tab: &Tab, url: &str, to: u32) -> Result<bool> {
+    match open_product_page(tab, url)? {
+        Some(page) if page.state == PageState::InCart => set_quantity(tab, to),
+        Some(page) => Ok(page.packs() == Some(to)),
+        None => Ok(false),
+    }
+}
+
+/// Make the cart match the list. A line that can't be fixed doesn't stop the
+/// others; what's left to do by hand is returned.
+fn fix_cart_differences(tab: &Tab, diff: &cart::CartDiff) -> Vec<String> {
+    let mut unfixed = Vec::new();
+    let mut outcome = |result: Result<bool>, done_message: String, todo: String| match result {
+        Ok(true) => println!("   ✅ {}", done_message),
+        Ok(false) => {
+            println!("   \x1b[33m⚠️  Couldn't {}, fix it in the cart\x1b[0m", todo);
+            unfixed.push(todo);
+        }
+        Err(e) => {
+            println!("   \x1b[33m⚠️  Couldn't {} ({}), fix it in the cart\x1b[0m", todo, e);
+            unfixed.push(format!("{} ({})", todo, e));
+        }
+    };
+    let add = |wanted: &cart::Expected| -> Result<bool> {
+        Ok(match add_product_by_url(tab, &wanted.url)? {
+            AddOutcome::Added => {
+                wait_for_packs(tab, Some(0))?;
+                set_quantity(tab, wanted.packs)?
             }
-            
-            return 'not_found';
-        })()
-    "#;
-    
-    let result = tab.evaluate(add_to_cart_js, false)?;
+            AddOutcome::InCart => set_quantity(tab, wanted.packs)?,
+            AddOutcome::NotAdded => false,
+        })
+    };
     
-    if let Some(value) = result.value {
-        match value.as_str() {
-            Some("added") => {
-                std::thread::sleep(Duration::from_secs(1));
-                return Ok(true);
-            }
-            Some("already_in_cart") => {
-                println!("   ℹ️  Item already in cart");
-                return Ok(true);
-            }
-            Some("out_of_stock") => {
-                println!("   ⚠️  Item is out of stock");
-                return Ok(false);
-            }
-            _ => return Ok(false),
+    for wanted in &diff.missing {
+        outcome(add(wanted), format!("Added {} × {}", wanted.packs, wanted.item), format!("add {} × {}", wanted.packs, wanted.item));
+    }
+    for (wanted, _) in &diff.wrong_quantity {
+        outcome(
+            change_cart_quantity(tab, &wanted.url, wanted.packs),
+            format!("Set {} to {}", wanted.item, wanted.packs),
+            format!("set {} to {}", wanted.item, wanted.packs),
+        );
+    }
+    for (wanted, line) in &diff.substituted {
+        outcome(cart::remove_line(tab, line), format!("Removed {}", line.name), format!("remove {}", line.name));
+        outcome(add(wanted), format!("Added {} × {}", wanted.packs, wanted.item), format!("add {} × {}", wanted.packs, wanted.item));
+    }
+    for line in &diff.extras {
+        outcome(cart::remove_line(tab, line), format!("Removed {}", line.name), format!("remove {}", line.name));
+    }
+    unfixed
+}
+
+/// What pressing "Add to Trolley" did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddOutcome {
+    Added,
+    /// The stepper was showing already: the product was in the cart before
+    InCart,
+    NotAdded,
+}
+
+fn add_product_by_url(tab: &Tab, url: &str) -> Result<AddOutcome> {
+    match open_product_page(tab, url)? {
+        Some(page) => add_from_page(tab, &page),
+        None => Ok(AddOutcome::NotAdded),
+    }
+}
+
+/// Click the main product's add button on a page that's already open
+fn add_from_page(tab: &Tab, page: &ProductPage) -> Result<AddOutcome> {
+    match page.state {
+        PageState::CanAdd => {
+            let click_js = product_js(r#"
+                const btn = product && product.querySelector(addButton);
+                if (!btn || btn.disabled) return false;
+                btn.click();
+                return true;
+            "#);
+            let result = tab.evaluate(&click_js, false)?;
+            std::thread::sleep(Duration::from_secs(1));
+            Ok(if matches!(result.value, Some(serde_json::Value::Bool(true))) { AddOutcome::Added } else { AddOutcome::NotAdded })
+        }
+        PageState::InCart => {
+            let shown = page.packs().map_or("an unknown number of".to_string(), |packs| packs.to_string());
+            println!("   ℹ️  Already in the cart with {} pack(s)", shown);
+            Ok(AddOutcome::InCart)
+        }
+        PageState::OutOfStock => {
+            println!("   ⚠️  Item is out of stock");
+            Ok(AddOutcome::NotAdded)
         }
+        PageState::NotFound => Ok(AddOutcome::NotAdded),
     }
-    
-    Ok(false)
 }
 
-fn show_cart_summary(tab: &Tab, added_count: usize, failed_items: &[&str]) -> Result<()> {
+fn show_cart_summary(report: &ShopReport, cart: Option<&cart::Cart>) {
     println!("\n{}", "=".repeat(60));
     println!("📊 SHOPPING SUMMARY");
     println!("{}", "=".repeat(60));
     
-    // Navigate to cart page
-    tab.navigate_to(&format!("{}/cart", SUPERVALU_BASE_URL))?;
-    std::thread::sleep(Duration::from_secs(3));
+    println!("\n✅ Successfully added: {} items", report.added_count);
     
-    // Get cart details
-    let cart_info_js = r#"
-        (() => {
-            const result = {
-                itemCount: 0,
-                subtotal: '',
-                items: []
-            };
-            
-            // Try to find item count
-            const countEl = document.querySelector('[class*="cart-count"], [class*="CartCount"], [aria-label*="items in cart"]');
-            if (countEl) {
-                const match = countEl.textContent.match(/\d+/);
-                if (match) result.itemCount = parseInt(match[0]);
-            }
-            
-            // Try to find subtotal
-            const subtotalEl = document.querySelector('[class*="subtotal"], [class*="Subtotal"], [class*="total-price"]');
-            if (subtotalEl) {
-                result.subtotal = subtotalEl.textContent.trim();
-            }
-            
-            // Get cart items
-            const cartItems = document.querySelectorAll('[class*="cart-item"], [class*="CartItem"], article[data-testid*="cart"]');
-            cartItems.forEach(item => {
-                const nameEl = item.querySelector('h3, h4, [class*="product-name"], [class*="ProductName"]');
-                const priceEl = item.querySelector('[class*="price"], [class*="Price"]');
-                const quantityEl = item.querySelector('input[type="number"], [class*="quantity"], select');
-                
-                if (nameEl) {
-                    result.items.push({
-                        name: nameEl.textContent.trim(),
-                        price: priceEl ? priceEl.textContent.trim() : '',
-                        quantity: quantityEl ? (quantityEl.value || quantityEl.textContent.trim()) : '1'
-                    });
-                }
-            });
-            
-            return JSON.stringify(result);
-        })()
-    "#;
-    
-    let cart_result = tab.evaluate(cart_info_js, false)?;
+    match &report.delivery_slot {
+        Some(slot) => println!("\n🚚 Delivery slot: {}", slot),
+        None => println!("\n\x1b[33m🚚 No delivery slot booked by the run, choose one before checkout\x1b[0m"),
+    }
     
-    if let Some(value) = cart_result.value {
-        if let Some(json_str) = value.as_str() {
-        #[derive(serde::Deserialize)]
-        struct CartInfo {
-            #[serde(rename = "itemCount")]
-            item_count: usize,
-            subtotal: String,
-            items: Vec<CartItem>,
+    if !report.substitutions.is_empty() {
+        println!("\n🔁 Substituted {} items (adjust the recipes if needed):", report.substitutions.len());
+        for substitution in &report.substitutions {
+            println!("   - {}", substitution);
         }
-        
-        #[derive(serde::Deserialize)]
-        struct CartItem {
-            name: String,
-            price: String,
-            quantity: String,
+    }
+
+    if !report.already_in_cart.is_empty() {
+        println!("\nℹ️  {} items were already in the cart:", report.already_in_cart.len());
+        for item in &report.already_in_cart {
+            println!("   - {}", item);
         }
-        
-        if let Ok(cart_info) = serde_json::from_str::<CartInfo>(json_str) {
-            println!("\n✅ Successfully added: {} items", added_count);
-            
-            if !failed_items.is_empty() {
-                // Separate items with no links from other failures
-                let no_link_items: Vec<&&str> = failed_items.iter()
-                    .filter(|item| item.contains("(no link)"))
-                    .collect();
-                let other_failed: Vec<&&str> = failed_items.iter()
-                    .filter(|item| !item.contains("(no link)"))
-                    .collect();
-                
-                if !no_link_items.is_empty() {
-                    println!("\n\x1b[33m⏭️  Skipped {} items (no links provided):\x1b[0m", no_link_items.len());
-                    for item in no_link_items {
-                        let clean_name = item.replace(" (no link)", "");
-                        println!("   - {}", clean_name);
-                    }
-                }
-                
-                if !other_failed.is_empty() {
-                    println!("\n❌ Failed to add {} items:", other_failed.len());
-                    for item in other_failed {
-                        println!("   - {}", item);
-                    }
-                }
-            }
-            
-            if !cart_info.items.is_empty() {
-                println!("\n🛒 Cart Contents:");
-                for item in &cart_info.items {
-                    println!("   • {} (qty: {}) - {}", item.name, item.quantity, item.price);
-                }
+    }
+
+    if !report.budget_skipped.is_empty() {
+        println!("\n\x1b[33m💸 Skipped {} items for budget reasons:\x1b[0m", report.budget_skipped.len());
+        for item in &report.budget_skipped {
+            println!("   - {}", item);
+        }
+    }
+
+    if !report.other_stores.is_empty() {
+        println!("\n🏪 {} items to buy elsewhere:", report.other_stores.len());
+        for item in &report.other_stores {
+            println!("   - {}", item);
+        }
+    }
+
+    if !report.failed_items.is_empty() {
+        // Separate items with no links from other failures
+        let no_link_items: Vec<&String> = report.failed_items.iter()
+            .filter(|item| item.contains("(no link)"))
+            .collect();
+        let other_failed: Vec<&String> = report.failed_items.iter()
+            .filter(|item| !item.contains("(no link)"))
+            .collect();
+
+        if !no_link_items.is_empty() {
+            println!("\n\x1b[33m⏭️  Skipped {} items (no links provided):\x1b[0m", no_link_items.len());
+            for item in no_link_items {
+                let clean_name = item.replace(" (no link)", "");
+                println!("   - {}", clean_name);
             }
-            
-            if !cart_info.subtotal.is_empty() {
-                println!("\n💰 Subtotal: {}", cart_info.subtotal);
+        }
+
+        if !other_failed.is_empty() {
+            println!("\n❌ Failed to add {} items:", other_failed.len());
+            for item in other_failed {
+                println!("   - {}", item);
             }
         }
+    }
+    
+    if !report.unfixed.is_empty() {
+        println!("\n\x1b[33m🔧 Couldn't fix {} cart lines, do it in the cart:\x1b[0m", report.unfixed.len());
+        for line in &report.unfixed {
+            println!("   - {}", line);
         }
     }
     
+    if let Some(cart) = cart {
+        println!();
+        cart::print(cart);
+    }
+    println!();
+    reconcile_budget(&report.budget, cart.and_then(|cart| cart.subtotal));
+    
     println!("\n{}", "=".repeat(60));
+}
+
+/// Compare our running estimate with what the store says the cart costs.
+/// They differ when prices changed since scraping or an add didn't go through.
+fn reconcile_budget(budget: &Budget, subtotal: Option<f64>) {
+    println!("🧮 Estimated cart total: €{:.2} (€{:.2} in the cart before the run)", budget.spent, budget.in_cart);
+    
+    if let Some(subtotal) = subtotal
+        && (subtotal - budget.spent).abs() >= 0.01
+    {
+        println!("   \x1b[33m⚠️  Cart subtotal differs from the estimate by €{:.2}\x1b[0m", subtotal - budget.spent);
+    }
     
-    Ok(())
-}
\ No newline at end of file
+    if let Some(cap) = budget.cap {
+        let total = subtotal.unwrap_or(budget.spent);
+        if total > cap {
+            println!("   \x1b[31m❌ Cart is €{:.2} over the €{:.2} budget\x1b[0m", total - cap, cap);
+        } else {
+            println!("   💶 €{:.2} left of the €{:.2} budget", cap - total, cap);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Priority;
+
+    #[test]
+    fn budget_counts_what_was_in_the_cart() {
+        let mut budget = Budget::new(Some(20.0), 15.0);
+        assert!(budget.allows(Some(2.5), 2));
+        assert!(!budget.allows(Some(2.5), 3));
+        assert!(!budget.allows(None, 1));
+
+        budget.spend(Some(2.5), 2);
+        assert_eq!(budget.spent, 20.0);
+        assert!(!budget.allows(Some(0.01), 1));
+
+        let uncapped = Budget::new(None, 0.0);
+        assert!(uncapped.allows(None, 1));
+    }
+
+    #[test]
+    fn drops_optional_items_once_the_cart_is_over_the_cap() {
+        let chocolate = ShoppingItem { priority: Priority::Nice, ..ShoppingItem::named("chocolate") };
+        let milk = ShoppingItem::named("milk");
+
+        let mut budget = Budget::new(Some(100.0), 90.0);
+        budget.spend(Some(4.0), 1);
+        // The subtotal read back wins over the estimate
+        assert_eq!(budget.after_add(Some(98.0), &milk), None);
+        assert_eq!(budget.spent, 98.0);
+        assert_eq!(budget.after_add(Some(103.5), &chocolate), Some(OverCap::Drop));
+        assert_eq!(budget.after_add(Some(103.5), &milk), Some(OverCap::Stop));
+        // No subtotal: the estimate stands
+        budget.spent = 99.0;
+        assert_eq!(budget.after_add(None, &chocolate), None);
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::names::clean_name;
+
+// Substitution rules from `config/substitutions.conf`, consulted by `shop`
+// once every stored option for an item has failed:
+//
+//   buffalo_mozzarella -> mozzarella
+//   coriander_leaves -> parsley, dill
+//   never baby*
+//   never tag:baby
+//
+// Names are compared as db keys (`buffalo mozzarella` == `buffalo_mozzarella`)
+// and may use `*` as a wildcard. `tag:` matches the shopping list item's tags.
+
+struct Rule {
+    pattern: String,
+    substitutes: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct SubstitutionRules {
+    rules: Vec<Rule>,
+    never: Vec<String>,
+    never_tags: Vec<String>,
+}
+
+pub fn substitutions_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("substitutions.conf")
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+impl SubstitutionRules {
+    /// Missing file means no substitutions
+    pub fn load(db_path: &str) -> Result<Self> {
+        let path = substitutions_path(db_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .context(format!("Failed to read substitution rules from {:?}", path))?;
+
+        let mut rules = Self::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(pattern) = line.strip_prefix("never ") {
+                match pattern.trim().strip_prefix("tag:") {
+                    Some(tag) => rules.never_tags.push(tag.trim().to_lowercase()),
+                    None => rules.never.push(clean_name(pattern)),
+                }
+            } else if let Some((pattern, substitutes)) = line.split_once("->") {
+                let substitutes: Vec<String> = substitutes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if pattern.trim().is_empty() || substitutes.is_empty() {
+                    bail!("{:?} line {}: expected 'item -> substitute'", path, number + 1);
+                }
+                rules.rules.push(Rule {
+                    pattern: clean_name(pattern),
+                    substitutes,
+                });
+            } else {
+                bail!("{:?} line {}: expected 'item -> substitute' or 'never item'", path, number + 1);
+            }
+        }
+
+        Ok(rules)
+    }
+
+    fn matches(pattern: &str, names: &[&str]) -> bool {
+        names.iter().any(|name| glob_match(pattern, name))
+    }
+
+    /// `names` are the ways to refer to the item: list name and resolved db key
+    pub fn is_protected(&self, names: &[&str], tags: &[String]) -> bool {
+        self.never.iter().any(|pattern| Self::matches(pattern, names))
+            || tags.iter().any(|tag| self.never_tags.iter().any(|never| glob_match(never, &tag.to_lowercase())))
+    }
+
+    /// Substitutes to try, in rule order; empty for protected items
+    pub fn substitutes_for(&self, names: &[&str], tags: &[String]) -> Vec<&str> {
+        if self.is_protected(names, tags) {
+            return Vec::new();
+        }
+
+        self.rules
+            .iter()
+            .filter(|rule| Self::matches(&rule.pattern, names))
+            .flat_map(|rule| rule.substitutes.iter().map(String::as_str))
+            .collect()
+    }
+}
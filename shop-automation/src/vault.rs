@@ -0,0 +1,225 @@
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Encrypted session file: the saved cookies and optionally the account
+// password, sealed with XChaCha20-Poly1305 under a key derived (Argon2id)
+// from a passphrase or the contents of a key file. The secret comes from
+// SHOP_AUTOMATION_KEY_FILE, then SHOP_AUTOMATION_PASSPHRASE, then a prompt
+// on the terminal, so no OS keychain is needed.
+
+/// What the vault holds
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Secrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Saved browser cookies, as written by `shopper`
+    #[serde(default)]
+    pub cookies: Vec<serde_json::Value>,
+}
+
+/// The file on disk; everything but `kdf` is base64
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    kdf: String,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+const KDF: &str = "argon2id";
+
+/// Key and salt of the vault unlocked in this run, so the passphrase is asked once
+static UNLOCKED: Mutex<Option<([u8; 32], Vec<u8>)>> = Mutex::new(None);
+
+fn secret(confirm: bool) -> Result<Vec<u8>> {
+    if let Ok(key_file) = env::var("SHOP_AUTOMATION_KEY_FILE") {
+        let key = fs::read(&key_file).context(format!("Failed to read key file {}", key_file))?;
+        if key.is_empty() {
+            bail!("Key file {} is empty", key_file);
+        }
+        return Ok(key);
+    }
+    if let Ok(passphrase) = env::var("SHOP_AUTOMATION_PASSPHRASE") {
+        return Ok(passphrase.into_bytes());
+    }
+
+    let passphrase = rpassword::prompt_password("🔑 Session vault passphrase: ")
+        .context("Failed to read the passphrase (set SHOP_AUTOMATION_PASSPHRASE or SHOP_AUTOMATION_KEY_FILE for unattended runs)")?;
+    if passphrase.is_empty() {
+        bail!("Empty passphrase");
+    }
+    if confirm && rpassword::prompt_password("🔑 Repeat passphrase: ")? != passphrase {
+        bail!("Passphrases don't match");
+    }
+    Ok(passphrase.into_bytes())
+}
+
+fn derive_key(secret: &[u8], salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+pub fn load(path: &Path) -> Result<Secrets> {
+    let json = fs::read_to_string(path).context(format!("Failed to read session vault {:?}", path))?;
+    let sealed: Sealed = serde_json::from_str(&json).context(format!("Failed to parse session vault {:?}", path))?;
+    if sealed.kdf != KDF {
+        bail!("Session vault {:?} uses an unknown key derivation: {}", path, sealed.kdf);
+    }
+    let salt = BASE64.decode(&sealed.salt)?;
+
+    let mut unlocked = UNLOCKED.lock().expect("vault lock poisoned");
+    let key = match unlocked.as_ref() {
+        Some((key, unlocked_salt)) if *unlocked_salt == salt => *key,
+        _ => derive_key(&secret(false)?, &salt)?,
+    };
+
+    let secrets = open(&key, &sealed).context(format!("Can't open the session vault {:?}", path))?;
+    *unlocked = Some((key, salt));
+    Ok(secrets)
+}
+
+fn open(key: &[u8; 32], sealed: &Sealed) -> Result<Secrets> {
+    let nonce = BASE64.decode(&sealed.nonce)?;
+    let data = BASE64.decode(&sealed.data)?;
+    if nonce.len() != 24 {
+        bail!("the nonce is damaged");
+    }
+
+    let plain = XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(&nonce), data.as_ref())
+        .map_err(|_| anyhow!("wrong passphrase or key file, or the file was changed"))?;
+    serde_json::from_slice(&plain).context("the contents are damaged")
+}
+
+fn seal(key: &[u8; 32], salt: &[u8], secrets: &Secrets) -> Result<Sealed> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = XChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce, serde_json::to_vec(secrets)?.as_ref())
+        .map_err(|_| anyhow!("Failed to encrypt the session vault"))?;
+    Ok(Sealed {
+        kdf: KDF.to_string(),
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        data: BASE64.encode(data),
+    })
+}
+
+/// Seal `secrets` into `path`, reusing the key the vault was opened with
+pub fn save(path: &Path, secrets: &Secrets) -> Result<()> {
+    let mut unlocked = UNLOCKED.lock().expect("vault lock poisoned");
+    let (key, salt) = match unlocked.as_ref() {
+        Some((key, salt)) => (*key, salt.clone()),
+        None => {
+            if path.exists() {
+                bail!("Open the session vault before writing it");
+            }
+            let mut salt = vec![0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            (derive_key(&secret(true)?, &salt)?, salt)
+        }
+    };
+
+    let sealed = seal(&key, &salt, secrets)?;
+
+    let temp_path = path.with_extension("vault.tmp");
+    write_private(&temp_path, serde_json::to_string_pretty(&sealed)?.as_bytes())?;
+    fs::rename(&temp_path, path).context(format!("Failed to replace {:?}", path))?;
+    *unlocked = Some((key, salt));
+    Ok(())
+}
+
+/// Write a file only the current user can read (mode 0600 on Unix), for
+/// the vault and plaintext session cookies
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .context(format!("Failed to restrict {:?}", path))?;
+        }
+    }
+
+    let mut file = options.open(path).context(format!("Failed to write {:?}", path))?;
+    file.write_all(contents).context(format!("Failed to write {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets() -> Secrets {
+        Secrets {
+            email: Some("me@example.com".to_string()),
+            password: Some("hunter2".to_string()),
+            cookies: vec![serde_json::json!({ "name": "session", "value": "abc" })],
+        }
+    }
+
+    #[test]
+    fn opens_what_it_sealed() {
+        let key = derive_key(b"correct horse", b"0123456789abcdef").unwrap();
+        let sealed = seal(&key, b"0123456789abcdef", &secrets()).unwrap();
+
+        let opened = open(&key, &sealed).unwrap();
+        assert_eq!(opened.email.as_deref(), Some("me@example.com"));
+        assert_eq!(opened.password.as_deref(), Some("hunter2"));
+        assert_eq!(opened.cookies, secrets().cookies);
+    }
+
+    #[test]
+    fn refuses_the_wrong_passphrase() {
+        let salt = b"0123456789abcdef";
+        let sealed = seal(&derive_key(b"correct horse", salt).unwrap(), salt, &secrets()).unwrap();
+
+        let wrong = derive_key(b"battery staple", salt).unwrap();
+        assert!(open(&wrong, &sealed).is_err());
+    }
+
+    #[test]
+    fn refuses_a_changed_file() {
+        let key = derive_key(b"correct horse", b"0123456789abcdef").unwrap();
+        let mut sealed = seal(&key, b"0123456789abcdef", &secrets()).unwrap();
+
+        let mut data = BASE64.decode(&sealed.data).unwrap();
+        data[0] ^= 1;
+        sealed.data = BASE64.encode(data);
+        assert!(open(&key, &sealed).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_files_only_the_owner_can_read() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("shop-automation-vault-test-{}", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode, 0o600);
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::models::ShoppingList;
+
+// Weaning log in `config/weaning.yml`: a new single food every few days,
+// with earlier foods that went well repeated as a second meal. Foods for the
+// coming days go to the `== Baby ==` section of a menu or straight onto a
+// shopping list, tagged `baby`.
+
+/// Tag put on shopping list items for the baby
+pub const BABY_TAG: &str = "baby";
+
+/// Menu section the planner leaves for the baby
+const BABY_SECTION: &str = "Baby";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Introduction {
+    pub food: String,
+    pub date: NaiveDate,
+    /// "none" or what happened: "rash", "vomited", ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reaction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl Introduction {
+    fn had_reaction(&self) -> bool {
+        self.reaction
+            .as_deref()
+            .is_some_and(|r| !r.trim().is_empty() && !r.eq_ignore_ascii_case("none"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeaningLog {
+    #[serde(default = "default_every_days")]
+    pub every_days: i64,
+    /// Foods still to introduce, in order
+    #[serde(default)]
+    pub queue: Vec<String>,
+    #[serde(default)]
+    pub introduced: Vec<Introduction>,
+}
+
+fn default_every_days() -> i64 {
+    3
+}
+
+pub fn weaning_path(db_path: &str) -> PathBuf {
+    let db = Path::new(db_path);
+    db.parent().unwrap_or(db).join("weaning.yml")
+}
+
+impl WeaningLog {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read weaning log from {:?} (create it with a `queue:` of foods)", path))?;
+        serde_yaml::from_str(&content)
+            .context(format!("Failed to parse weaning log in {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let yaml = serde_yaml::to_string(self)?;
+        let temp_path = path.with_extension("yml.tmp");
+        fs::write(&temp_path, yaml)
+            .context(format!("Failed to write {:?}", temp_path))?;
+        fs::rename(&temp_path, path)
+            .context(format!("Failed to replace {:?}", path))?;
+        Ok(())
+    }
+
+    fn find(&self, food: &str) -> Option<&Introduction> {
+        self.introduced.iter().find(|i| i.food.eq_ignore_ascii_case(food))
+    }
+
+    fn last(&self) -> Option<&Introduction> {
+        self.introduced.iter().max_by_key(|i| i.date)
+    }
+
+    /// When the next new food is due; today if nothing has been introduced yet
+    pub fn next_date(&self, today: NaiveDate) -> NaiveDate {
+        self.last()
+            .map(|last| last.date + chrono::Duration::days(self.every_days))
+            .unwrap_or(today)
+    }
+
+    /// First queued food not introduced yet
+    pub fn next_food(&self) -> Option<&str> {
+        self.queue
+            .iter()
+            .find(|food| self.find(food).is_none())
+            .map(String::as_str)
+    }
+
+    /// The next food, once it's due and how the last one went has been recorded
+    pub fn new_food_due(&self, today: NaiveDate) -> Option<&str> {
+        let reaction_logged = self.last().is_none_or(|last| last.reaction.is_some());
+        self.next_food().filter(|_| reaction_logged && self.next_date(today) <= today)
+    }
+
+    /// A food that went well, for the second meal; the longest since it was introduced
+    /// comes first so everything gets repeated
+    pub fn repeat_food(&self) -> Option<&str> {
+        let last = self.last().map(|l| l.food.as_str());
+        self.introduced
+            .iter()
+            .filter(|i| !i.had_reaction() && Some(i.food.as_str()) != last)
+            .min_by_key(|i| i.date)
+            .or_else(|| self.last().filter(|l| !l.had_reaction()))
+            .map(|i| i.food.as_str())
+    }
+}
+
+pub fn status(db_path: &str) -> Result<()> {
+    let log = WeaningLog::load(&weaning_path(db_path))?;
+    let today = Local::now().date_naive();
+
+    println!("👶 Weaning log ({} foods introduced)\n", log.introduced.len());
+    for intro in &log.introduced {
+        let reaction = match &intro.reaction {
+            Some(_) if intro.had_reaction() => format!("\x1b[31m⚠️  {}\x1b[0m", intro.reaction.as_deref().unwrap_or_default()),
+            Some(_) => "✅ no reaction".to_string(),
+            None => "❔ reaction not recorded".to_string(),
+        };
+        println!("   {}  {:<20} {}", intro.date, intro.food, reaction);
+        if let Some(notes) = &intro.notes {
+            println!("               {}", notes);
+        }
+    }
+
+    if let Some(last) = log.last()
+        && last.reaction.is_none()
+    {
+        println!("\n📝 Record how {} went: weaning react \"{}\" none", last.food, last.food);
+    }
+
+    let next_date = log.next_date(today);
+    match log.next_food() {
+        Some(food) if next_date <= today => println!("\n🥕 Next new food: {} (due today)", food),
+        Some(food) => println!("\n🥕 Next new food: {} on {} (in {} days)", food, next_date, (next_date - today).num_days()),
+        None => println!("\n🎉 Every queued food has been introduced; add more to `queue`"),
+    }
+    if let Some(food) = log.repeat_food() {
+        println!("🔁 Second meal: {}", food);
+    }
+
+    Ok(())
+}
+
+pub fn introduce(db_path: &str, food: &str, date: Option<NaiveDate>) -> Result<()> {
+    let path = weaning_path(db_path);
+    let mut log = WeaningLog::load(&path)?;
+    if let Some(existing) = log.find(food) {
+        bail!("{} was already introduced on {}", existing.food, existing.date);
+    }
+
+    let date = date.unwrap_or_else(|| Local::now().date_naive());
+    if let Some(last) = log.last()
+        && last.had_reaction()
+        && (date - last.date).num_days() < log.every_days
+    {
+        println!("⚠️  {} caused a reaction recently; consider waiting before something new", last.food);
+    }
+
+    log.introduced.push(Introduction {
+        food: food.to_string(),
+        date,
+        reaction: None,
+        notes: None,
+    });
+    log.save(&path)?;
+
+    println!("✅ Introduced {} on {}", food, date);
+    println!("   Next new food due {}", log.next_date(date));
+    Ok(())
+}
+
+pub fn react(db_path: &str, food: &str, reaction: &str, notes: Option<String>) -> Result<()> {
+    let path = weaning_path(db_path);
+    let mut log = WeaningLog::load(&path)?;
+    let Some(intro) = log.introduced.iter_mut().find(|i| i.food.eq_ignore_ascii_case(food)) else {
+        bail!("{} hasn't been introduced yet", food);
+    };
+
+    intro.reaction = Some(reaction.to_string());
+    if notes.is_some() {
+        intro.notes = notes;
+    }
+    let flagged = intro.had_reaction();
+    log.save(&path)?;
+
+    if flagged {
+        println!("⚠️  Recorded a reaction to {}: {}. It won't be suggested as a second meal.", food, reaction);
+    } else {
+        println!("✅ Recorded no reaction to {}", food);
+    }
+    Ok(())
+}
+
+/// Put the next new food (when it's due) and a repeat food on a `.menu` (its
+/// `== Baby ==` section, as the planner writes it) or a shopping list YAML
+pub fn add_to(db_path: &str, target: &Path) -> Result<()> {
+    let log = WeaningLog::load(&weaning_path(db_path))?;
+    let today = Local::now().date_naive();
+    let new_food = log.new_food_due(today);
+    if new_food.is_none()
+        && let Some(food) = log.next_food()
+    {
+        match log.last() {
+            Some(last) if last.reaction.is_none() => {
+                println!("⏸  Not adding {} yet: record how {} went first (weaning react)", food, last.food)
+            }
+            _ => println!("⏸  Not adding {} yet: it's due on {}", food, log.next_date(today)),
+        }
+    }
+
+    let foods: Vec<&str> = new_food.into_iter().chain(log.repeat_food()).collect();
+    if foods.is_empty() {
+        println!("Nothing to add: no new food is due and nothing has been introduced");
+        return Ok(());
+    }
+
+    let added = if target.extension().is_some_and(|ext| ext == "menu") {
+        add_to_menu(target, &foods)?
+    } else {
+        add_to_shopping_list(target, &foods)?
+    };
+
+    if added.is_empty() {
+        println!("✅ {} already has {}", target.display(), foods.join(" and "));
+    } else {
+        println!("✅ Added {} to {}", added.join(" and "), target.display());
+    }
+    Ok(())
+}
+
+fn add_to_menu(path: &Path, foods: &[&str]) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .context(format!("Failed to read menu {:?}", path))?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    let is_baby = |line: &String| {
+        let line = line.trim();
+        line.starts_with("==") && line.trim_matches('=').trim().eq_ignore_ascii_case(BABY_SECTION)
+    };
+    let start = match lines.iter().position(is_baby) {
+        Some(start) => start,
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("== {} ==", BABY_SECTION));
+            lines.push(String::new());
+            lines.len() - 2
+        }
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|l| l.trim().starts_with("=="))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    let section = lines[start..end].join("\n").to_lowercase();
+
+    // After the section's last line of content
+    let mut insert_at = (start + 1..end).rev().find(|&i| !lines[i].trim().is_empty()).map_or(start + 2, |i| i + 1);
+    insert_at = insert_at.min(lines.len());
+
+    let mut added = Vec::new();
+    for food in foods {
+        if section.contains(&format!("@{}", food.to_lowercase())) {
+            continue;
+        }
+        lines.insert(insert_at, format!("- @{}{{}}", food));
+        insert_at += 1;
+        added.push(food.to_string());
+    }
+
+    if !added.is_empty() {
+        fs::write(path, lines.join("\n") + "\n").context(format!("Failed to write {:?}", path))?;
+    }
+    Ok(added)
+}
+
+fn add_to_shopping_list(path: &Path, foods: &[&str]) -> Result<Vec<String>> {
+    let (mut content, existing) = if path.exists() {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read shopping list {:?}", path))?;
+        let list: ShoppingList = serde_yaml::from_str(&content)
+            .context(format!("Failed to parse shopping list {:?}", path))?;
+        (content, list.items)
+    } else {
+        ("items:\n".to_string(), Vec::new())
+    };
+
+    // Appended as text so the rest of the list stays as the template wrote
+    // it, indented like the entries already there
+    if existing.is_empty() {
+        content = content.replace("items: []", "items:");
+    }
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    let indent = content
+        .lines()
+        .find(|line| line.trim_start().starts_with("- "))
+        .map_or("  ".to_string(), |line| line[..line.len() - line.trim_start().len()].to_string());
+    let mut added = Vec::new();
+    for food in foods {
+        if existing.iter().any(|item| item.name.eq_ignore_ascii_case(food)) {
+            continue;
+        }
+        content.push_str(&format!(
+            "{indent}- name: {food}\n{indent}  tags: [{tag}]\n{indent}  note: weaning\n",
+            indent = indent,
+            food = food,
+            tag = BABY_TAG
+        ));
+        added.push(food.to_string());
+    }
+
+    if !added.is_empty() {
+        serde_yaml::from_str::<ShoppingList>(&content)
+            .context(format!("Adding to {:?} would leave it unreadable; add the foods by hand", path))?;
+        fs::write(path, content).context(format!("Failed to write {:?}", path))?;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 7, d).unwrap()
+    }
+
+    fn intro(food: &str, date: NaiveDate, reaction: Option<&str>) -> Introduction {
+        Introduction { food: food.to_string(), date, reaction: reaction.map(str::to_string), notes: None }
+    }
+
+    fn log(introduced: Vec<Introduction>) -> WeaningLog {
+        WeaningLog {
+            every_days: 3,
+            queue: ["carrots", "parsnip", "pear"].map(str::to_string).to_vec(),
+            introduced,
+        }
+    }
+
+    #[test]
+    fn next_food_is_the_first_queued_one_not_introduced() {
+        assert_eq!(log(vec![]).next_food(), Some("carrots"));
+        assert_eq!(log(vec![intro("Carrots", day(1), Some("none"))]).next_food(), Some("parsnip"));
+
+        let all = log(vec![intro("carrots", day(1), None), intro("parsnip", day(4), None), intro("pear", day(7), None)]);
+        assert_eq!(all.next_food(), None);
+    }
+
+    #[test]
+    fn next_date_counts_from_the_latest_introduction() {
+        assert_eq!(log(vec![]).next_date(day(9)), day(9));
+
+        let introduced = log(vec![intro("parsnip", day(4), None), intro("carrots", day(1), None)]);
+        assert_eq!(introduced.next_date(day(5)), day(7));
+    }
+
+    #[test]
+    fn new_food_waits_for_its_date_and_the_last_reaction() {
+        assert_eq!(log(vec![]).new_food_due(day(1)), Some("carrots"));
+
+        let recorded = log(vec![intro("carrots", day(1), Some("none"))]);
+        assert_eq!(recorded.new_food_due(day(3)), None);
+        assert_eq!(recorded.new_food_due(day(4)), Some("parsnip"));
+
+        let unrecorded = log(vec![intro("carrots", day(1), None)]);
+        assert_eq!(unrecorded.new_food_due(day(9)), None);
+    }
+
+    #[test]
+    fn repeat_food_skips_reactions_and_prefers_the_oldest() {
+        let several = log(vec![
+            intro("carrots", day(1), Some("rash")),
+            intro("parsnip", day(4), Some("none")),
+            intro("pear", day(7), None),
+        ]);
+        assert_eq!(several.repeat_food(), Some("parsnip"));
+
+        // With only one food, the latest is repeated unless it caused a reaction
+        assert_eq!(log(vec![intro("carrots", day(1), None)]).repeat_food(), Some("carrots"));
+        assert_eq!(log(vec![intro("carrots", day(1), Some("vomited"))]).repeat_food(), None);
+    }
+
+    #[test]
+    fn adds_foods_to_the_end_of_the_baby_section() {
+        let path = std::env::temp_dir().join(format!("shop-automation-weaning-menu-{}.menu", std::process::id()));
+        fs::write(&path, "== Day 1 ==\n\n- @pasta{}\n\n== Baby ==\n\n- @carrots{}\n\n== Notes ==\nbatch cook\n").unwrap();
+
+        let added = add_to_menu(&path, &["parsnip", "carrots"]).unwrap();
+        assert_eq!(added, ["parsnip"]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "== Day 1 ==\n\n- @pasta{}\n\n== Baby ==\n\n- @carrots{}\n- @parsnip{}\n\n== Notes ==\nbatch cook\n"
+        );
+
+        // A menu without one gets the section at the end
+        fs::write(&path, "== Day 1 ==\n- @pasta{}\n").unwrap();
+        add_to_menu(&path, &["pear"]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "== Day 1 ==\n- @pasta{}\n\n== Baby ==\n\n- @pear{}\n");
+        fs::remove_file(&path).unwrap();
+    }
+}